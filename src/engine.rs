@@ -200,7 +200,7 @@ impl Engine {
         };
         self.chain.swapchain = new_swapchain;
         self.render_pass.framebuffers =
-            gl::get_framebuffers(&new_images, self.render_pass().render_pass());
+            gl::get_framebuffers(self.device(), &new_images, self.render_pass().render_pass());
 
         Ok(new_dimensions)
     }
@@ -209,9 +209,10 @@ impl Engine {
         &mut self,
         vs: Arc<ShaderModule>,
         fs: Arc<ShaderModule>,
+        desc: gl::PipelineDesc,
         descriptor_wites: impl IntoIterator<Item = WriteDescriptorSet>,
     ) -> MatID {
-        let mat = Material::new(vs, fs, descriptor_wites, self);
+        let mat = Material::new(vs, fs, desc, descriptor_wites, self);
 
         let id = MatID(NEXT_MATERIAL_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed));
 
@@ -224,6 +225,18 @@ impl Engine {
         &self.materials[id]
     }
 
+    ///Point a material at new resources, keeping its shaders and pipeline
+    pub fn update_material(
+        &mut self,
+        id: &MatID,
+        descriptor_wites: impl IntoIterator<Item = WriteDescriptorSet>,
+    ) {
+        self.materials
+            .get_mut(id)
+            .unwrap()
+            .set_descriptors(descriptor_wites);
+    }
+
     pub fn update_viewport(&mut self, new_dimensions: [f32; 2]) {
         self.viewport.dimensions = new_dimensions;
 
@@ -239,6 +252,7 @@ impl Engine {
                 m.fs.clone(),
                 pass.clone(),
                 viewport.clone(),
+                &m.desc,
             )
         }
     }
@@ -297,8 +311,8 @@ pub struct Pass {
 }
 impl Pass {
     pub fn new(chain: &Chain, device: Arc<Device>) -> Self {
-        let render_pass = gl::get_render_pass(device, chain.swapchain());
-        let framebuffers = gl::get_framebuffers(&chain.images, render_pass.clone());
+        let render_pass = gl::get_render_pass(device.clone(), chain.swapchain());
+        let framebuffers = gl::get_framebuffers(device, &chain.images, render_pass.clone());
         //create the render pass and buffers
         Self {
             render_pass,
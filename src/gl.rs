@@ -7,17 +7,25 @@ use vulkano::command_buffer::{
 };
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::device::{Device, DeviceCreateInfo, Queue, QueueCreateInfo};
+use vulkano::format::Format;
 use vulkano::image::view::ImageView;
-use vulkano::image::{ImageUsage, SwapchainImage};
+use vulkano::image::{AttachmentImage, ImageAccess, ImageUsage, SwapchainImage};
 use vulkano::instance::{Instance, InstanceCreateInfo};
 
 use vulkano::buffer::TypedBufferAccess;
 use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
 use vulkano::device::DeviceExtensions;
-use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, BlendFactor, BlendOp, ColorBlendState,
+};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::{InputAssemblyState, PrimitiveTopology};
+use vulkano::pipeline::graphics::rasterization::{CullMode, RasterizationState};
 use vulkano::pipeline::graphics::vertex_input::BuffersDefinition;
 use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
-use vulkano::pipeline::{ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::pipeline::{
+    ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint, StateMode,
+};
 use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
 use vulkano::shader::ShaderModule;
 use vulkano::swapchain::Swapchain;
@@ -100,16 +108,23 @@ pub fn get_render_pass(device: Arc<Device>, swapchain: Arc<Swapchain<Window>>) -
                 store: Store,
                 format: swapchain.image_format(),  // set the format the same as the swapchain
                 samples: 1,
+            },
+            depth: {
+                load: Clear,
+                store: DontCare,
+                format: DEPTH_FORMAT,
+                samples: 1,
             }
         },
         pass: {
             color: [color],
-            depth_stencil: {}
+            depth_stencil: {depth}
         }
     )
     .unwrap()
 }
 pub fn get_framebuffers(
+    device: Arc<Device>,
     images: &[Arc<SwapchainImage<Window>>],
     render_pass: Arc<RenderPass>,
 ) -> Vec<Arc<Framebuffer>> {
@@ -117,10 +132,21 @@ pub fn get_framebuffers(
         .iter()
         .map(|image| {
             let view = ImageView::new_default(image.clone()).unwrap();
+            //Only used by materials with a depth test, so never needs to outlive the pass
+            let depth = ImageView::new_default(
+                AttachmentImage::transient(
+                    device.clone(),
+                    image.dimensions().width_height(),
+                    DEPTH_FORMAT,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
             Framebuffer::new(
                 render_pass.clone(),
                 FramebufferCreateInfo {
-                    attachments: vec![view],
+                    attachments: vec![view, depth],
                     ..Default::default()
                 },
             )
@@ -128,6 +154,65 @@ pub fn get_framebuffers(
         })
         .collect::<Vec<_>>()
 }
+///How a pipeline combines what it draws with what is already in the frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    ///Overwrite, ignoring alpha
+    Opaque,
+    ///Mix by the source alpha
+    Alpha,
+    ///Add to the frame, for glows and particles
+    Additive,
+    ///Like [`BlendMode::Alpha`] for textures whose colours are already multiplied by alpha
+    Premultiplied,
+}
+
+impl BlendMode {
+    fn color_blend_state(self) -> ColorBlendState {
+        let state = ColorBlendState::new(1);
+
+        match self {
+            BlendMode::Opaque => state,
+            BlendMode::Alpha => state.blend_alpha(),
+            BlendMode::Additive => state.blend_additive(),
+            BlendMode::Premultiplied => state.blend(AttachmentBlend {
+                color_op: BlendOp::Add,
+                color_source: BlendFactor::One,
+                color_destination: BlendFactor::OneMinusSrcAlpha,
+                alpha_op: BlendOp::Add,
+                alpha_source: BlendFactor::One,
+                alpha_destination: BlendFactor::OneMinusSrcAlpha,
+            }),
+        }
+    }
+}
+
+/// The fixed function state of a material's pipeline, kept so it can be rebuilt the same way
+#[derive(Clone, Copy, Debug)]
+pub struct PipelineDesc {
+    pub blend: BlendMode,
+    ///Sprites flip by scaling negatively, which turns their triangles around, so leave them at
+    ///[`CullMode::None`]
+    pub cull: CullMode,
+    pub topology: PrimitiveTopology,
+    ///Test against and write to the depth buffer, if set
+    pub depth_test: Option<CompareOp>,
+}
+
+impl Default for PipelineDesc {
+    fn default() -> Self {
+        Self {
+            blend: BlendMode::Alpha,
+            cull: CullMode::None,
+            topology: PrimitiveTopology::TriangleList,
+            depth_test: None,
+        }
+    }
+}
+
+///Format of the depth buffer every framebuffer has
+pub const DEPTH_FORMAT: Format = Format::D16_UNORM;
+
 // As for the pipeline, let's initialize the viewport with our window dimensions:
 pub fn get_pipeline(
     device: Arc<Device>,
@@ -135,13 +220,29 @@ pub fn get_pipeline(
     fs: Arc<ShaderModule>,
     render_pass: Arc<RenderPass>,
     viewport: Viewport,
+    desc: &PipelineDesc,
 ) -> Arc<GraphicsPipeline> {
+    let depth_stencil = match desc.depth_test {
+        Some(compare_op) => DepthStencilState {
+            depth: Some(DepthState {
+                enable_dynamic: false,
+                compare_op: StateMode::Fixed(compare_op),
+                write_enable: StateMode::Fixed(true),
+            }),
+            ..DepthStencilState::disabled()
+        },
+        None => DepthStencilState::disabled(),
+    };
+
     GraphicsPipeline::start()
         .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
         .vertex_shader(vs.entry_point("main").unwrap(), ())
-        .input_assembly_state(InputAssemblyState::new())
+        .input_assembly_state(InputAssemblyState::new().topology(desc.topology))
         .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([viewport]))
         .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .rasterization_state(RasterizationState::new().cull_mode(desc.cull))
+        .color_blend_state(desc.blend.color_blend_state())
+        .depth_stencil_state(depth_stencil)
         .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
         .build(device.clone())
         .unwrap()
@@ -172,7 +273,7 @@ pub fn get_draw_command_buffers(
                 .begin_render_pass(
                     framebuffer.clone(),
                     SubpassContents::Inline,
-                    vec![[0.0, 0.0, 1.0, 1.0].into()],
+                    vec![[0.0, 0.0, 1.0, 1.0].into(), 1f32.into()],
                 )
                 .unwrap()
                 .bind_pipeline_graphics(pipeline.clone())
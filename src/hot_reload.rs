@@ -0,0 +1,161 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+///How often watched files are checked, as checking every frame would hit the disk 60 times a second
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A file that we want to know about changes to, found by checking its modified time
+///
+/// There's no file watching crate available, so this is polled instead
+pub struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl WatchedFile {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_owned();
+        let modified = modified(&path);
+
+        Self { path, modified }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    ///If the file has been modified since it was last checked
+    ///
+    ///A missing file doesn't count, as editors often delete and rewrite files when saving
+    pub fn changed(&mut self) -> bool {
+        match modified(&self.path) {
+            Some(m) if Some(m) != self.modified => {
+                self.modified = Some(m);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    ///Forget about any changes, such as ones we made by saving the file ourselves
+    pub fn sync(&mut self) {
+        self.modified = modified(&self.path);
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchID(usize);
+
+/// Watches a set of asset files, only checking them every [`POLL_INTERVAL`]
+///
+/// Whoever loaded an asset is in charge of reloading it, and can report how that went with
+/// [`HotReloader::report`] so it shows up in the UI instead of stopping the game
+pub struct HotReloader {
+    files: Vec<WatchedFile>,
+    last_poll: Instant,
+    messages: Vec<String>,
+}
+
+impl HotReloader {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            last_poll: Instant::now(),
+            messages: Vec::new(),
+        }
+    }
+
+    pub fn watch(&mut self, path: impl AsRef<Path>) -> WatchID {
+        self.files.push(WatchedFile::new(path));
+
+        WatchID(self.files.len() - 1)
+    }
+
+    pub fn path(&self, id: WatchID) -> &Path {
+        self.files[id.0].path()
+    }
+
+    ///Every watched file that changed since the last poll, or nothing if it's too soon to check
+    pub fn poll(&mut self) -> Vec<WatchID> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        self.files
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, f)| f.changed().then_some(WatchID(i)))
+            .collect()
+    }
+
+    pub fn report(&mut self, message: impl Into<String>) {
+        self.messages.push(message.into());
+    }
+
+    ///Show what has been reloaded, and anything that failed to
+    pub fn ui(&mut self, ui: &imgui::Ui) {
+        if self.messages.is_empty() {
+            return;
+        }
+
+        imgui::Window::new("Hot reload")
+            .size([300.0, 100.0], imgui::Condition::FirstUseEver)
+            .build(ui, || {
+                for m in &self.messages {
+                    ui.text_wrapped(m);
+                }
+
+                if ui.button("Clear") {
+                    self.messages.clear();
+                }
+            });
+    }
+}
+
+impl Default for HotReloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watched_file() {
+        let path = std::env::temp_dir().join("mole_man_test.watched.txt");
+        std::fs::write(&path, "one").unwrap();
+
+        let mut file = WatchedFile::new(&path);
+        assert!(!file.changed());
+
+        let touch = |secs| {
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap()
+        };
+
+        touch(1000);
+        assert!(file.changed());
+        assert!(!file.changed());
+
+        //Changes we made ourselves can be skipped
+        touch(2000);
+        file.sync();
+        assert!(!file.changed());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!file.changed());
+    }
+}
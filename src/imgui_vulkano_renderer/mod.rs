@@ -118,11 +118,18 @@ impl ImGuiRenderer {
                     store: Store,
                     format: format,
                     samples: 1,
+                },
+                // Unused, but has to match the pass the UI is drawn in
+                depth: {
+                    load: Load,
+                    store: DontCare,
+                    format: crate::gl::DEPTH_FORMAT,
+                    samples: 1,
                 }
             },
             pass: {
                 color: [color],
-                depth_stencil: {}
+                depth_stencil: {depth}
             }
         )
         .unwrap();
@@ -1,9 +1,11 @@
 pub mod compute;
 pub mod engine;
 pub mod gl;
+pub mod hot_reload;
 pub mod imgui_vulkano_renderer;
 pub mod material;
 mod mesh;
+pub mod pdn;
 pub mod physics;
 pub mod player;
 pub mod rendering;
@@ -13,9 +15,9 @@ mod tilemap;
 pub mod transform;
 pub mod uniform;
 
+use bevy_ecs::event::Events;
 pub use bevy_ecs::prelude as ecs;
 use bevy_ecs::schedule::Stage;
-use vulkano::pipeline::Pipeline;
 
 use std::sync::{Arc, Mutex};
 
@@ -85,6 +87,8 @@ pub struct InputEvent {
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, ecs:: StageLabel)]
 enum SystemTrigger {
+    ///Swaps event buffers, before anything that sends or reads events
+    UpdateEvents,
     OnUpdate,
     OnKeyboardInput,
 }
@@ -165,7 +169,13 @@ fn main() -> ! {
 
     let desert_sprite_sheet = Texture::load("assets/tileset.png", &engine);
 
-    let moleman_sprite_sheet = Texture::load("assets/moleman.png", &engine);
+    let (mole_sprite, moleman_sprite_sheet) =
+        sprite::load_sprite("assets/moleman.png", &engine).expect("failed to load mole sprite");
+
+    //The tileset config is watched by its editor, so it can tell its own saves apart
+    let mut hot_reload = hot_reload::HotReloader::new();
+    let desert_sheet_watch = hot_reload.watch("assets/tileset.png");
+    let moleman_sheet_watch = hot_reload.watch("assets/moleman.png");
 
     let mut dragging = false;
 
@@ -209,37 +219,43 @@ fn main() -> ! {
         .expect("Failed to initialize renderer");
 
     //create the tilemap for the desert tile map then create it's material
-    let config = Arc::new(Mutex::new(tilemap::TilemapSpriteConfig::new_or_load(
-        "assets/tileset.png.tileset.json",
-        16,
-        8,
-    )));
+    let config =
+        tilemap::TilemapSpriteConfig::new_or_load("assets/tileset.png.tileset.json", 16, 8)
+            .unwrap_or_else(|e| {
+                //Still start up, so the config can be fixed from the editor
+                eprintln!("Failed to load tileset config, starting empty: {}", e);
+                tilemap::TilemapSpriteConfig::new(16, 8)
+            });
+    let config = Arc::new(Mutex::new(config));
 
     let desert =
-        tilemap::TilemapRenderer::new(config.clone(), desert_sprite_sheet.clone(), &engine);
+        tilemap::TilemapRenderer::new(config.clone(), desert_sprite_sheet.clone(), 16, 16, &engine);
 
     let desert_mat = desert.create_material(&mut engine, &transform);
 
+    //a second map using the same tileset, sitting to the right of the desert
+    let cave =
+        tilemap::TilemapRenderer::new(config.clone(), desert_sprite_sheet.clone(), 16, 16, &engine);
+
+    let cave_mat = cave.create_material(&mut engine, &transform);
+
     let mut config_editor = tilemap::sprite_config_editor::TilemapSpriteConfigEditor::new(
         &mut renderer,
         config.clone(),
         desert_sprite_sheet.clone(),
     );
 
+    let mut tilemap_editor = tilemap::TilemapEditor::new();
+
     let mole_mat = sprite::create_sprite_material(&mut engine, &moleman_sprite_sheet, &transform);
+    let mut sprite_batcher = sprite::SpriteBatcher::new(engine.device());
 
-    let mole_sprite = Arc::new(sprite::Sprite {
-        grid_width: 3,
-        grid_height: 1,
-        tile_width: 32,
-        tile_height: 32,
-    });
-
-    let mole_sprite_data = sprite::SpriteData {
-        sprite: mole_sprite.clone(),
-        tile_x: 0,
-        tile_y: 0,
-    };
+    let mole_sprite_data = sprite::SpriteData::new(mole_sprite.clone());
+
+    let mole_run = Arc::new(
+        sprite::Animation::load("assets/moleman.run.anim.json")
+            .expect("failed to load mole animation"),
+    );
 
     //    let m = engine.get_material(&desert_mat);
 
@@ -262,11 +278,23 @@ fn main() -> ! {
         .insert(rendering::Renderer {
             material: desert_mat,
         })
+        .insert(rendering::SortingLayer(String::from("Background")))
+        .insert(transform::Position(0.0, 0.0))
+        .insert(tilemap::Pathfinder::new(Default::default()))
         .id();
 
+    world
+        .spawn()
+        .insert(cave)
+        .insert(rendering::Renderer { material: cave_mat })
+        .insert(rendering::SortingLayer(String::from("Background")))
+        .insert(transform::Position(16.0, 0.0))
+        .insert(tilemap::Pathfinder::new(Default::default()));
+
     world
         .spawn()
         .insert(mole_sprite_data)
+        .insert(sprite::Animator::new(mole_run.clone()))
         .insert(rendering::Renderer { material: mole_mat })
         .insert(transform::Position(0.0, 0.0))
         .insert(physics::Velocity(0.0, 0.0))
@@ -274,15 +302,32 @@ fn main() -> ! {
 
     world.insert_resource(Time { t: 0.0, dt: 0.1 });
 
+    let mut sorting_layers = rendering::SortingLayers::default();
+    sorting_layers.set_y_sort(rendering::DEFAULT_LAYER, true);
+    world.insert_resource(sorting_layers);
+    world.insert_resource(Events::<tilemap::TileChanged>::default());
+    world.insert_resource(Events::<sprite::AnimationFinished>::default());
+
     // Create a new Schedule, which defines an execution strategy for Systems
     let mut schedule = ecs::Schedule::default();
     // Add a Stage to our schedule. Each Stage in a schedule runs all of its systems
     // before moving on to the next Stage
 
+    // Events sent during one update are still readable in the next, as long as the buffers are
+    // only swapped before either, so readers that run before the writer don't miss anything
+    schedule.add_stage(
+        SystemTrigger::UpdateEvents,
+        ecs::SystemStage::parallel()
+            .with_system(Events::<tilemap::TileChanged>::update_system)
+            .with_system(Events::<sprite::AnimationFinished>::update_system),
+    );
+
     schedule.add_stage(
         SystemTrigger::OnUpdate,
         ecs::SystemStage::parallel()
             .with_system(tilemap::tilemap_on_update)
+            .with_system(tilemap::pathfinding_on_tile_changed)
+            .with_system(sprite::animation_on_update)
             .with_system(transform::bobble_on_update)
             .with_system(physics::on_update),
     );
@@ -302,6 +347,10 @@ fn main() -> ! {
             Event::MainEventsCleared => {
                 world.get_resource_mut::<Time>().unwrap().progress();
 
+                schedule
+                    .get_stage_mut::<ecs::SystemStage>(&SystemTrigger::UpdateEvents)
+                    .unwrap()
+                    .run(&mut world);
                 schedule
                     .get_stage_mut::<ecs::SystemStage>(&SystemTrigger::OnUpdate)
                     .unwrap()
@@ -454,24 +503,72 @@ fn main() -> ! {
                     .prepare_frame(imgui.io_mut(), engine.surface().window())
                     .unwrap();
 
+                for changed in hot_reload.poll() {
+                    let path = hot_reload.path(changed).display().to_string();
+
+                    let tex = match Texture::try_load(&path, &engine) {
+                        Ok(tex) => tex,
+                        Err(e) => {
+                            hot_reload.report(format!("Failed to reload {}: {}", path, e));
+                            continue;
+                        }
+                    };
+
+                    if changed == desert_sheet_watch {
+                        //Every tilemap uses the desert tileset for now
+                        for (mut tilemap, renderer) in world
+                            .query::<(&mut tilemap::TilemapRenderer, &rendering::Renderer)>()
+                            .iter_mut(&mut world)
+                        {
+                            tilemap.set_texture(
+                                tex.clone(),
+                                &mut engine,
+                                &renderer.material,
+                                &transform,
+                            );
+                        }
+
+                        config_editor.set_texture(&mut renderer, tex);
+                    } else if changed == moleman_sheet_watch {
+                        sprite::update_sprite_material(&mut engine, &mole_mat, &tex, &transform);
+                    }
+
+                    hot_reload.report(format!("Reloaded {}", path));
+                }
+
                 let ui = imgui.frame();
 
+                hot_reload.ui(&ui);
+
                 imgui::Window::new("Tilemap Data Editor")
                     .size([300.0, 110.0], imgui::Condition::FirstUseEver)
                     .build(&ui, || config_editor.run(&ui));
                 //get entity will not panic if no entity present
-                if let Some(i) = world.get_entity(inspecting) {
-                    if let Some(tilemap) = i.get::<tilemap::TilemapRenderer>() {
+                if let Some(mut i) = world.get_entity_mut(inspecting) {
+                    //Borrows of different components don't overlap, so both can be held at once
+                    let mut pathfinder = i.get_mut::<tilemap::Pathfinder>();
+                    if let Some(mut tilemap) = i.get_mut::<tilemap::TilemapRenderer>() {
                         imgui::Window::new("Tilemap - desert")
-                            .size([200.0, 200.0], imgui::Condition::FirstUseEver)
+                            .size([300.0, 500.0], imgui::Condition::FirstUseEver)
                             .build(&ui, || {
                                 let [wx, wy] = ui.window_pos();
 
                                 let l = ui.get_window_draw_list();
                                 let w = 15.0;
                                 let h = 15.0;
-                                for x in 0..16 {
-                                    for y in 0..16 {
+                                let grid = tilemap.grid();
+
+                                //Where each cell is drawn in the window, flipping y as window
+                                //coordinates increase downwards
+                                let view = tilemap::GridTransform {
+                                    origin: [10.0 + wx, 30.0 + wy + h * grid.height() as f32],
+                                    cell_size: [w, -h],
+                                    width: grid.width(),
+                                    height: grid.height(),
+                                };
+
+                                for x in 0..grid.width() {
+                                    for y in 0..grid.height() {
                                         if let tilemap::Tile::Filled(o) = *tilemap.tile(x, y) {
                                             fn col(e: bool) -> u8 {
                                                 if e {
@@ -505,17 +602,11 @@ fn main() -> ! {
                                                 col(o.contains(tilemap::Orientation::SE)),
                                             );
 
-                                            //Y coordinates need to be flipped as we draw from top to bottom,
-                                            //and so y coordinates increase as we decrease in window space
+                                            let [bot_left, _, top_right, _] =
+                                                view.cell_corners(x, y);
                                             l.add_rect_filled_multicolor(
-                                                [
-                                                    10.0 + wx + w * x as f32,
-                                                    30.0 + wy + h * (16 - y) as f32,
-                                                ],
-                                                [
-                                                    10.0 + wx + w * (x + 1) as f32,
-                                                    30.0 + wy + h * (16 - (y + 1)) as f32,
-                                                ],
+                                                bot_left,
+                                                top_right,
                                                 col_upr_left,
                                                 col_upr_right,
                                                 col_bot_right,
@@ -524,6 +615,36 @@ fn main() -> ! {
                                         }
                                     }
                                 }
+
+                                //Outline the cells the editor is working on
+                                //the selection may have been made on a bigger map
+                                let (min, max) = tilemap_editor.selection();
+                                let clamp = |(x, y): (usize, usize)| {
+                                    (x.min(grid.width()), y.min(grid.height()))
+                                };
+                                let (top_left, bot_right) =
+                                    view.cells_to_world_rect(clamp(min), clamp(max));
+                                l.add_rect(
+                                    top_left,
+                                    bot_right,
+                                    imgui::ImColor32::from_rgb(255, 255, 0),
+                                )
+                                .build();
+
+                                ui.set_cursor_pos([10.0, 40.0 + h * grid.height() as f32]);
+
+                                tilemap_editor.run(&ui, &mut tilemap, pathfinder.as_deref_mut());
+
+                                //Join the centres of the cells along the path the editor just found
+                                for step in tilemap_editor.path().windows(2) {
+                                    l.add_line(
+                                        view.cell_center(step[0].0, step[0].1),
+                                        view.cell_center(step[1].0, step[1].1),
+                                        imgui::ImColor32::from_rgb(0, 255, 255),
+                                    )
+                                    .thickness(2.0)
+                                    .build();
+                                }
                             });
                     }
                 }
@@ -548,7 +669,7 @@ fn main() -> ! {
                         .begin_render_pass(
                             framebuffer.clone(),
                             SubpassContents::Inline,
-                            vec![[0.0, 0.0, 0.0, 1.0].into()],
+                            vec![[0.0, 0.0, 0.0, 1.0].into(), 1f32.into()],
                         )
                         .unwrap();
 
@@ -564,36 +685,62 @@ fn main() -> ! {
 
                     //render pass started, can now issue draw instructions
 
-                    for (renderer, tilemap) in world
-                        .query::<(&rendering::Renderer, &tilemap::TilemapRenderer)>()
-                        .iter(&mut world)
+                    let layers = world
+                        .get_resource::<rendering::SortingLayers>()
+                        .unwrap()
+                        .clone();
+                    let mut drawables = Vec::new();
+
+                    for (renderer, tilemap, pos, scale, layer, z) in world
+                        .query::<(
+                            &rendering::Renderer,
+                            &tilemap::TilemapRenderer,
+                            &transform::Position,
+                            Option<&transform::Scale>,
+                            Option<&rendering::SortingLayer>,
+                            Option<&rendering::ZIndex>,
+                        )>()
+                        .iter(&world)
                     {
-                        let e = engine.get_material(&renderer.material);
-                        e.bind(&mut builder, &*square);
-                        e.draw(&mut builder, &*square, tilemap.instance_count());
+                        drawables.push((
+                            layers.key(layer, z, pos.1),
+                            rendering::Drawable::Tilemap {
+                                material: renderer.material,
+                                push_constants: tilemap.get_push_constants(pos, scale),
+                                instances: tilemap.instance_count(),
+                            },
+                        ));
                     }
 
-                    for (renderer, sprite_data, pos) in world
+                    for (renderer, sprite_data, pos, scale, rotation, layer, z) in world
                         .query::<(
                             &rendering::Renderer,
                             &sprite::SpriteData,
                             &transform::Position,
+                            Option<&transform::Scale>,
+                            Option<&transform::Rotation>,
+                            Option<&rendering::SortingLayer>,
+                            Option<&rendering::ZIndex>,
                         )>()
-                        .iter(&mut world)
+                        .iter(&world)
                     {
-                        let e = engine.get_material(&renderer.material);
-
-                        e.bind(&mut builder, &*square);
-
-                        builder.push_constants(
-                            e.pipeline.layout().clone(),
-                            0,
-                            sprite_data.get_push_constants(pos),
-                        );
-
-                        e.draw(&mut builder, &*square, 1);
+                        drawables.push((
+                            layers.key(layer, z, pos.1),
+                            rendering::Drawable::Sprite {
+                                material: renderer.material,
+                                instance: sprite_data.get_instance(pos, scale, rotation),
+                            },
+                        ));
                     }
 
+                    rendering::draw_sorted(
+                        drawables,
+                        &mut builder,
+                        &engine,
+                        &*square,
+                        &mut sprite_batcher,
+                    );
+
                     renderer
                         .draw_commands(
                             &mut builder,
@@ -709,29 +856,35 @@ fn main() -> ! {
                 //alter the tilemap;
                 //first get mouse pos in tilemap, then alter the tilemap
                 let s = engine.surface().window().inner_size();
-
-                let mut x = last_mouse_pos.unwrap().x as f32 / s.width as f32;
-                let mut y = last_mouse_pos.unwrap().y as f32 / s.height as f32;
-
-                x -= 0.5;
-                y -= 0.5;
-                x *= 2.0;
-                y *= 2.0;
-
-                let pos = transform.screen_to_world(x, y);
-
-                if pos.x > 0.0 && pos.y > 0.0 {
-                    let grid_x = pos.x.floor() as usize;
-                    let grid_y = pos.y.floor() as usize;
-
-                    if grid_x < 16 && grid_y < 16 {
+                let mouse = last_mouse_pos.unwrap();
+
+                let pos = transform.window_to_world(
+                    mouse.x as f32,
+                    mouse.y as f32,
+                    s.width as f32,
+                    s.height as f32,
+                );
+
+                //find the map under the mouse
+                for (entity, mut tilemap, map_pos, scale) in world
+                    .query::<(
+                        ecs::Entity,
+                        &mut tilemap::TilemapRenderer,
+                        &transform::Position,
+                        Option<&transform::Scale>,
+                    )>()
+                    .iter_mut(&mut world)
+                {
+                    let cell = tilemap
+                        .transform(map_pos, scale)
+                        .world_to_cell([pos.x, pos.y]);
+
+                    if let Some((grid_x, grid_y)) = cell {
                         println!("grid {}, {}", grid_x, grid_y);
 
-                        if let Some(mut i) = world.get_entity_mut(inspecting) {
-                            if let Some(mut tilemap) = i.get_mut::<tilemap::TilemapRenderer>() {
-                                tilemap.toggle(grid_x, grid_y);
-                            }
-                        }
+                        tilemap.toggle(grid_x, grid_y);
+                        inspecting = entity;
+                        break;
                     }
                 }
             }
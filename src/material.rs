@@ -7,11 +7,16 @@ use vulkano::{
     shader::ShaderModule,
 };
 
-use crate::{engine::Engine, gl, mesh::Mesh};
+use crate::{
+    engine::Engine,
+    gl::{self, PipelineDesc},
+    mesh::Mesh,
+};
 
 pub struct Material {
     pub vs: Arc<ShaderModule>,
     pub fs: Arc<ShaderModule>,
+    pub desc: PipelineDesc,
     descriptors: Arc<PersistentDescriptorSet>,
 
     pub pipeline: Arc<GraphicsPipeline>,
@@ -21,6 +26,7 @@ impl Material {
     pub fn new(
         vs: Arc<ShaderModule>,
         fs: Arc<ShaderModule>,
+        desc: PipelineDesc,
         descriptor_wites: impl IntoIterator<Item = WriteDescriptorSet>,
         engine: &Engine,
     ) -> Self {
@@ -30,11 +36,13 @@ impl Material {
             fs.clone(),
             engine.render_pass().render_pass(),
             engine.viewport().clone(),
+            &desc,
         );
 
         Self {
             vs: vs.clone(),
             fs: fs.clone(),
+            desc,
 
             //we are creating the layout for set 0
             descriptors: PersistentDescriptorSet::new(
@@ -75,4 +83,21 @@ impl Material {
     pub fn descriptors(&self) -> Arc<PersistentDescriptorSet> {
         self.descriptors.clone()
     }
+
+    ///Replace the descriptor set, such as after a texture has been reloaded
+    pub fn set_descriptors(
+        &mut self,
+        descriptor_wites: impl IntoIterator<Item = WriteDescriptorSet>,
+    ) {
+        self.descriptors = PersistentDescriptorSet::new(
+            self.pipeline
+                .layout()
+                .set_layouts()
+                .first()
+                .unwrap()
+                .clone(),
+            descriptor_wites,
+        )
+        .unwrap();
+    }
 }
@@ -0,0 +1,838 @@
+//! Reading Paint.NET `.pdn` files, so the source art can be used without exporting it
+//!
+//! A `.pdn` is an XML header, then the document written by .NET's `BinaryFormatter`
+//! ([MS-NRBF](https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-nrbf/)), then the
+//! pixels of each layer in chunks
+
+use std::{collections::HashMap, fmt, io::Read, path::Path};
+
+use image::RgbaImage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Additive,
+    ColorBurn,
+    ColorDodge,
+    Reflect,
+    Glow,
+    Overlay,
+    Difference,
+    Negation,
+    Lighten,
+    Darken,
+    Screen,
+    Xor,
+}
+
+impl BlendMode {
+    ///From the name of the .NET class Paint.NET uses for it, like
+    ///`PaintDotNet.UserBlendOps+MultiplyBlendOp`
+    fn from_class(class: &str) -> Option<Self> {
+        let name = class.rsplit(['+', '.']).next()?.strip_suffix("BlendOp")?;
+
+        Some(match name {
+            "Normal" => BlendMode::Normal,
+            "Multiply" => BlendMode::Multiply,
+            "Additive" => BlendMode::Additive,
+            "ColorBurn" => BlendMode::ColorBurn,
+            "ColorDodge" => BlendMode::ColorDodge,
+            "Reflect" => BlendMode::Reflect,
+            "Glow" => BlendMode::Glow,
+            "Overlay" => BlendMode::Overlay,
+            "Difference" => BlendMode::Difference,
+            "Negation" => BlendMode::Negation,
+            "Lighten" => BlendMode::Lighten,
+            "Darken" => BlendMode::Darken,
+            "Screen" => BlendMode::Screen,
+            "Xor" => BlendMode::Xor,
+            _ => return None,
+        })
+    }
+
+    ///Blend a single colour channel of `top` onto `bottom`, before alpha is taken into account
+    fn channel(self, bottom: u8, top: u8) -> u8 {
+        let (b, t) = (bottom as u32, top as u32);
+
+        (match self {
+            BlendMode::Normal => t,
+            BlendMode::Multiply => b * t / 255,
+            BlendMode::Additive => (b + t).min(255),
+            BlendMode::ColorBurn if t == 0 => 0,
+            BlendMode::ColorBurn => 255u32.saturating_sub((255 - b) * 255 / t),
+            BlendMode::ColorDodge if t == 255 => 255,
+            BlendMode::ColorDodge => (b * 255 / (255 - t)).min(255),
+            BlendMode::Reflect if t == 255 => 255,
+            BlendMode::Reflect => (b * b / (255 - t)).min(255),
+            BlendMode::Glow if b == 255 => 255,
+            BlendMode::Glow => (t * t / (255 - b)).min(255),
+            BlendMode::Overlay if b < 128 => 2 * b * t / 255,
+            BlendMode::Overlay => 255 - 2 * (255 - b) * (255 - t) / 255,
+            BlendMode::Difference => b.abs_diff(t),
+            BlendMode::Negation => 255 - (255i32 - b as i32 - t as i32).unsigned_abs(),
+            BlendMode::Lighten => b.max(t),
+            BlendMode::Darken => b.min(t),
+            BlendMode::Screen => 255 - (255 - b) * (255 - t) / 255,
+            BlendMode::Xor => b ^ t,
+        }) as u8
+    }
+
+    ///Draw `top` over `bottom` the way Paint.NET does, with `opacity` applied to `top` first
+    pub fn blend(self, bottom: [u8; 4], top: [u8; 4], opacity: u8) -> [u8; 4] {
+        let bottom_a = bottom[3] as f32 / 255.0;
+        let top_a = top[3] as f32 / 255.0 * opacity as f32 / 255.0;
+
+        //How much of the result is only the bottom, only the top, or both blended together
+        let only_bottom = bottom_a * (1.0 - top_a);
+        let both = bottom_a * top_a;
+        let only_top = top_a - both;
+        let total = only_bottom + top_a;
+
+        if total <= 0.0 {
+            return [0; 4];
+        }
+
+        let mut out = [0; 4];
+        for i in 0..3 {
+            let blended = self.channel(bottom[i], top[i]) as f32;
+            let c = (bottom[i] as f32 * only_bottom + top[i] as f32 * only_top + blended * both)
+                / total;
+
+            out[i] = c.round().clamp(0.0, 255.0) as u8;
+        }
+        out[3] = (total * 255.0).round() as u8;
+
+        out
+    }
+}
+
+pub struct PdnLayer {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: u8,
+    pub blend_mode: BlendMode,
+    pub image: RgbaImage,
+}
+
+pub struct PdnDocument {
+    pub width: u32,
+    pub height: u32,
+    ///From the bottom layer up
+    pub layers: Vec<PdnLayer>,
+}
+
+impl PdnDocument {
+    pub fn layer(&self, name: &str) -> Option<&PdnLayer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    ///Combine the visible layers into one image, as Paint.NET would show it
+    pub fn flatten(&self) -> RgbaImage {
+        let mut out = RgbaImage::new(self.width, self.height);
+
+        for layer in self.layers.iter().filter(|l| l.visible) {
+            for (o, p) in out.pixels_mut().zip(layer.image.pixels()) {
+                o.0 = layer.blend_mode.blend(o.0, p.0, layer.opacity);
+            }
+        }
+
+        out
+    }
+}
+
+#[derive(Debug)]
+pub enum PdnError {
+    Io(std::io::Error),
+    ///Doesn't start with the `PDN3` magic number
+    NotPdn,
+    ///Cut short or otherwise not what Paint.NET writes
+    Malformed(String),
+}
+
+impl fmt::Display for PdnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PdnError::Io(e) => write!(f, "{}", e),
+            PdnError::NotPdn => write!(f, "not a Paint.NET image"),
+            PdnError::Malformed(e) => write!(f, "bad Paint.NET image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PdnError {}
+
+impl From<std::io::Error> for PdnError {
+    fn from(e: std::io::Error) -> Self {
+        PdnError::Io(e)
+    }
+}
+
+///Biggest width or height we will read, well above anything we'd use as a texture
+const MAX_PDN_SIZE: i64 = 16384;
+
+fn malformed(message: impl Into<String>) -> PdnError {
+    PdnError::Malformed(message.into())
+}
+
+///A value read from the `BinaryFormatter` stream
+#[derive(Debug, Clone)]
+enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    ///Another record, looked up by its object id
+    Ref(i32),
+    ///A value we have no use for, like a date
+    Other,
+}
+
+#[derive(Debug, Default)]
+struct Object {
+    class: String,
+    members: Vec<(String, Value)>,
+    ///Elements, if this is an array
+    items: Vec<Value>,
+}
+
+///How a class's member values are written
+#[derive(Debug, Clone)]
+enum MemberType {
+    ///Written inline, with no record header
+    Primitive(u8),
+    ///Written as a record
+    Record,
+}
+
+#[derive(Debug, Clone)]
+struct ClassInfo {
+    name: String,
+    members: Vec<String>,
+    types: Vec<MemberType>,
+}
+
+/// Reads records from an MS-NRBF stream into a graph of [`Object`]s
+///
+/// Only what is needed for a Paint.NET document is supported
+struct Nrbf<'a> {
+    data: &'a [u8],
+    pos: usize,
+    classes: HashMap<i32, ClassInfo>,
+    objects: HashMap<i32, Object>,
+    root: i32,
+    ///Null records that stand for more than one value still to be handed out
+    pending_nulls: usize,
+}
+
+///What a record turned out to be
+enum Record {
+    Value(Value),
+    End,
+}
+
+impl<'a> Nrbf<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], PdnError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| malformed("ended early"))?;
+        let b = &self.data[self.pos..end];
+        self.pos = end;
+
+        Ok(b)
+    }
+
+    fn u8(&mut self) -> Result<u8, PdnError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i32(&mut self) -> Result<i32, PdnError> {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, PdnError> {
+        //Length is written 7 bits at a time, lowest first
+        let mut len = 0usize;
+        for shift in (0..35).step_by(7) {
+            let b = self.u8()?;
+            len |= ((b & 0x7f) as usize) << shift;
+            if b & 0x80 == 0 {
+                break;
+            }
+        }
+
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    fn primitive(&mut self, kind: u8) -> Result<Value, PdnError> {
+        let le = |b: &[u8]| {
+            let mut v = [0; 8];
+            v[..b.len()].copy_from_slice(b);
+            u64::from_le_bytes(v)
+        };
+
+        Ok(match kind {
+            1 => Value::Bool(self.u8()? != 0),
+            2 => Value::Int(self.u8()? as i64),
+            10 => Value::Int(self.u8()? as i8 as i64),
+            7 => Value::Int(le(self.bytes(2)?) as i16 as i64),
+            14 => Value::Int(le(self.bytes(2)?) as i64),
+            8 => Value::Int(self.i32()? as i64),
+            15 => Value::Int(le(self.bytes(4)?) as i64),
+            9 | 16 => Value::Int(le(self.bytes(8)?) as i64),
+            11 => Value::Float(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()) as f64),
+            6 => Value::Float(f64::from_le_bytes(self.bytes(8)?.try_into().unwrap())),
+            12 | 13 => {
+                self.bytes(8)?;
+                Value::Other
+            }
+            5 | 18 => Value::String(self.string()?),
+            3 => {
+                //A UTF-8 character, which is 1 to 4 bytes long
+                let first = self.u8()?;
+                self.bytes((first.leading_ones() as usize).saturating_sub(1))?;
+                Value::Other
+            }
+            17 => Value::Null,
+            k => return Err(malformed(format!("unknown primitive type {}", k))),
+        })
+    }
+
+    fn class_info(&mut self) -> Result<(i32, String, Vec<String>), PdnError> {
+        let id = self.i32()?;
+        let name = self.string()?;
+        let count = self.i32()?;
+        let members = (0..count)
+            .map(|_| self.string())
+            .collect::<Result<_, _>>()?;
+
+        Ok((id, name, members))
+    }
+
+    fn member_types(&mut self, count: usize) -> Result<Vec<MemberType>, PdnError> {
+        let kinds = self.bytes(count)?.to_vec();
+
+        kinds
+            .into_iter()
+            .map(|kind| {
+                Ok(match kind {
+                    0 => MemberType::Primitive(self.u8()?),
+                    //Primitive arrays name their element type
+                    7 => {
+                        self.u8()?;
+                        MemberType::Record
+                    }
+                    3 => {
+                        self.string()?;
+                        MemberType::Record
+                    }
+                    4 => {
+                        self.string()?;
+                        self.i32()?;
+                        MemberType::Record
+                    }
+                    1 | 2 | 5 | 6 => MemberType::Record,
+                    k => return Err(malformed(format!("unknown member type {}", k))),
+                })
+            })
+            .collect()
+    }
+
+    ///The next value of a class member or array element
+    fn value(&mut self) -> Result<Value, PdnError> {
+        if self.pending_nulls > 0 {
+            self.pending_nulls -= 1;
+            return Ok(Value::Null);
+        }
+
+        match self.record()? {
+            Record::Value(v) => Ok(v),
+            Record::End => Err(malformed("ended in the middle of an object")),
+        }
+    }
+
+    fn object(&mut self, id: i32, class: ClassInfo) -> Result<Value, PdnError> {
+        let mut members = Vec::with_capacity(class.members.len());
+
+        for (name, kind) in class.members.iter().zip(&class.types) {
+            let value = match kind {
+                MemberType::Primitive(p) => self.primitive(*p)?,
+                MemberType::Record => self.value()?,
+            };
+            members.push((name.clone(), value));
+        }
+
+        self.objects.insert(
+            id,
+            Object {
+                class: class.name.clone(),
+                members,
+                items: Vec::new(),
+            },
+        );
+        self.classes.insert(id, class);
+
+        Ok(Value::Ref(id))
+    }
+
+    fn array(&mut self, id: i32, len: usize, primitive: Option<u8>) -> Result<Value, PdnError> {
+        let mut items = Vec::with_capacity(len.min(4096));
+
+        for _ in 0..len {
+            items.push(match primitive {
+                Some(p) => self.primitive(p)?,
+                None => self.value()?,
+            });
+        }
+
+        self.objects.insert(
+            id,
+            Object {
+                items,
+                ..Default::default()
+            },
+        );
+
+        Ok(Value::Ref(id))
+    }
+
+    fn record(&mut self) -> Result<Record, PdnError> {
+        let kind = self.u8()?;
+
+        let value = match kind {
+            0 => {
+                self.root = self.i32()?;
+                //Header id and version
+                self.bytes(12)?;
+                return self.record();
+            }
+            1 => {
+                let id = self.i32()?;
+                let metadata = self.i32()?;
+                let class = self
+                    .classes
+                    .get(&metadata)
+                    .cloned()
+                    .ok_or_else(|| malformed(format!("unknown class {}", metadata)))?;
+                self.object(id, class)?
+            }
+            2 | 3 => {
+                let (id, name, members) = self.class_info()?;
+                if kind == 3 {
+                    self.i32()?;
+                }
+                let types = vec![MemberType::Record; members.len()];
+                self.object(
+                    id,
+                    ClassInfo {
+                        name,
+                        members,
+                        types,
+                    },
+                )?
+            }
+            4 | 5 => {
+                let (id, name, members) = self.class_info()?;
+                let types = self.member_types(members.len())?;
+                if kind == 5 {
+                    self.i32()?;
+                }
+                self.object(
+                    id,
+                    ClassInfo {
+                        name,
+                        members,
+                        types,
+                    },
+                )?
+            }
+            6 => {
+                let id = self.i32()?;
+                let s = self.string()?;
+                self.objects.insert(
+                    id,
+                    Object {
+                        class: String::from("System.String"),
+                        members: vec![(String::new(), Value::String(s.clone()))],
+                        items: Vec::new(),
+                    },
+                );
+                Value::String(s)
+            }
+            7 => {
+                let id = self.i32()?;
+                let shape = self.u8()?;
+                let rank = self.i32()?.max(0) as usize;
+                let mut len = 1usize;
+                for _ in 0..rank {
+                    len = len.saturating_mul(self.i32()?.max(0) as usize);
+                }
+                //Arrays with offsets also give their lower bounds
+                if shape >= 3 {
+                    self.bytes(rank * 4)?;
+                }
+                let element = self.member_types(1)?.remove(0);
+                match element {
+                    MemberType::Primitive(p) => self.array(id, len, Some(p))?,
+                    MemberType::Record => self.array(id, len, None)?,
+                }
+            }
+            8 => {
+                let p = self.u8()?;
+                self.primitive(p)?
+            }
+            9 => Value::Ref(self.i32()?),
+            10 => Value::Null,
+            11 => return Ok(Record::End),
+            12 => {
+                self.i32()?;
+                self.string()?;
+                return self.record();
+            }
+            13 | 14 => {
+                let count = if kind == 13 {
+                    self.u8()? as usize
+                } else {
+                    self.i32()?.max(0) as usize
+                };
+                self.pending_nulls = count.saturating_sub(1);
+                Value::Null
+            }
+            15 => {
+                let id = self.i32()?;
+                let len = self.i32()?.max(0) as usize;
+                let p = self.u8()?;
+                if p == 2 {
+                    //Byte arrays can be huge, and we never need them
+                    self.bytes(len)?;
+                    self.objects.insert(id, Object::default());
+                    Value::Ref(id)
+                } else {
+                    self.array(id, len, Some(p))?
+                }
+            }
+            16 | 17 => {
+                let id = self.i32()?;
+                let len = self.i32()?.max(0) as usize;
+                self.array(id, len, None)?
+            }
+            k => return Err(malformed(format!("unsupported record type {}", k))),
+        };
+
+        Ok(Record::Value(value))
+    }
+
+    fn read(data: &'a [u8]) -> Result<(Self, usize), PdnError> {
+        let mut nrbf = Self {
+            data,
+            pos: 0,
+            classes: HashMap::new(),
+            objects: HashMap::new(),
+            root: 0,
+            pending_nulls: 0,
+        };
+
+        while let Record::Value(_) = nrbf.record()? {}
+
+        let end = nrbf.pos;
+        Ok((nrbf, end))
+    }
+
+    fn get(&self, id: i32) -> Result<&Object, PdnError> {
+        self.objects
+            .get(&id)
+            .ok_or_else(|| malformed(format!("missing object {}", id)))
+    }
+
+    ///Follow a reference to the object it points at
+    fn deref(&self, value: &Value) -> Result<&Object, PdnError> {
+        match value {
+            Value::Ref(id) => self.get(*id),
+            v => Err(malformed(format!("expected an object, found {:?}", v))),
+        }
+    }
+
+    ///A member of `object`, matched on the end of its name as inherited members are prefixed
+    ///with their class, like `Layer+properties`
+    fn member<'o>(&self, object: &'o Object, name: &str) -> Result<&'o Value, PdnError> {
+        object
+            .members
+            .iter()
+            .find(|(n, _)| n == name || n.rsplit('+').next() == Some(name))
+            .map(|(_, v)| v)
+            .ok_or_else(|| malformed(format!("{} has no {}", object.class, name)))
+    }
+
+    fn int(&self, object: &Object, name: &str) -> Result<i64, PdnError> {
+        match self.member(object, name)? {
+            Value::Int(i) => Ok(*i),
+            v => Err(malformed(format!(
+                "{} should be a number, found {:?}",
+                name, v
+            ))),
+        }
+    }
+
+    fn bool(&self, object: &Object, name: &str) -> Result<bool, PdnError> {
+        match self.member(object, name)? {
+            Value::Bool(b) => Ok(*b),
+            v => Err(malformed(format!(
+                "{} should be a bool, found {:?}",
+                name, v
+            ))),
+        }
+    }
+
+    fn string_member(&self, object: &Object, name: &str) -> Result<String, PdnError> {
+        match self.member(object, name)? {
+            Value::String(s) => Ok(s.clone()),
+            Value::Ref(id) => match self.get(*id)?.members.first() {
+                Some((_, Value::String(s))) => Ok(s.clone()),
+                _ => Err(malformed(format!("{} should be a string", name))),
+            },
+            Value::Null => Ok(String::new()),
+            v => Err(malformed(format!(
+                "{} should be a string, found {:?}",
+                name, v
+            ))),
+        }
+    }
+}
+
+///Reads the big endian numbers used by the layer data after the document
+fn be_u32(data: &[u8], pos: &mut usize) -> Result<u32, PdnError> {
+    let b = take(data, pos, 4)?;
+
+    Ok(u32::from_be_bytes(b.try_into().unwrap()))
+}
+
+///The next `len` bytes of the layer data
+fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], PdnError> {
+    let b = pos
+        .checked_add(len)
+        .and_then(|end| data.get(*pos..end))
+        .ok_or_else(|| malformed("layer data ended early"))?;
+    *pos += len;
+
+    Ok(b)
+}
+
+///Read a layer's pixels, which are split into chunks that are usually gzipped
+fn layer_pixels(data: &[u8], pos: &mut usize, len: usize) -> Result<Vec<u8>, PdnError> {
+    let compressed = match data.get(*pos) {
+        Some(0) => true,
+        Some(1) => false,
+        _ => return Err(malformed("unknown layer data format")),
+    };
+    *pos += 1;
+
+    let chunk_size = be_u32(data, pos)? as usize;
+    if chunk_size == 0 {
+        return Err(malformed("layer data has empty chunks"));
+    }
+
+    let mut pixels = vec![0; len];
+
+    for _ in 0..len.div_ceil(chunk_size) {
+        let number = be_u32(data, pos)? as usize;
+        let size = be_u32(data, pos)? as usize;
+        let chunk = take(data, pos, size)?;
+
+        let out = number
+            .checked_mul(chunk_size)
+            .filter(|&start| start < len)
+            .and_then(|start| pixels.get_mut(start..len.min(start.saturating_add(chunk_size))))
+            .ok_or_else(|| malformed(format!("layer chunk {} is out of range", number)))?;
+
+        if compressed {
+            flate2::read::GzDecoder::new(chunk).read_exact(out)?;
+        } else {
+            out.copy_from_slice(
+                chunk
+                    .get(..out.len())
+                    .ok_or_else(|| malformed("layer chunk is too short"))?,
+            );
+        }
+    }
+
+    Ok(pixels)
+}
+
+///Read a whole `.pdn` file
+pub fn read_pdn(data: &[u8]) -> Result<PdnDocument, PdnError> {
+    if !data.starts_with(b"PDN3") || data.len() < 7 {
+        return Err(PdnError::NotPdn);
+    }
+
+    //Skip the XML header, which only has a thumbnail and things we can also find later
+    let header_len = u32::from_le_bytes([data[4], data[5], data[6], 0]) as usize;
+    let body = data
+        .get(7 + header_len..)
+        .ok_or_else(|| malformed("header is cut short"))?;
+
+    let body = match body {
+        [0x00, 0x01, rest @ ..] => rest,
+        //Very old versions gzipped everything
+        _ => {
+            return Err(malformed(
+                "compressed documents from old versions aren't supported",
+            ))
+        }
+    };
+
+    let (nrbf, end) = Nrbf::read(body)?;
+
+    let document = nrbf.get(nrbf.root)?;
+    let size = |name| match nrbf.int(document, name)? {
+        s @ 1..=MAX_PDN_SIZE => Ok(s as u32),
+        s => Err(malformed(format!("{} of {} is out of range", name, s))),
+    };
+    let (width, height) = (size("width")?, size("height")?);
+
+    //The layer list is an ArrayList, which has more slots than layers
+    let list = nrbf.deref(nrbf.member(document, "layers")?)?;
+    let count = nrbf.int(list, "_size")?.max(0) as usize;
+    let items = nrbf.deref(nrbf.member(list, "_items")?)?;
+
+    let mut pos = end;
+    let mut layers = Vec::with_capacity(count);
+
+    for item in items.items.iter().take(count) {
+        let layer = nrbf.deref(item)?;
+        let properties = nrbf.deref(nrbf.member(layer, "properties")?)?;
+
+        //BitmapLayer's own properties hold the blend mode, Layer's hold the rest
+        let (layer_properties, blend_op) = match nrbf.member(properties, "blendOp") {
+            Ok(blend_op) => {
+                let layer_properties = layer
+                    .members
+                    .iter()
+                    .find(|(n, _)| n == "Layer+properties")
+                    .map(|(_, v)| v)
+                    .ok_or_else(|| malformed("layer has no properties"))?;
+                (nrbf.deref(layer_properties)?, Some(blend_op))
+            }
+            Err(_) => (properties, None),
+        };
+
+        let blend_mode = match blend_op {
+            Some(Value::Ref(id)) => {
+                let class = &nrbf.get(*id)?.class;
+                BlendMode::from_class(class)
+                    .ok_or_else(|| malformed(format!("unknown blend mode {}", class)))?
+            }
+            _ => BlendMode::Normal,
+        };
+
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or_else(|| malformed("image is too big"))?;
+        let mut pixels = layer_pixels(body, &mut pos, len)?;
+
+        //Paint.NET stores BGRA
+        for p in pixels.chunks_exact_mut(4) {
+            p.swap(0, 2);
+        }
+
+        layers.push(PdnLayer {
+            name: nrbf.string_member(layer_properties, "name")?,
+            visible: nrbf.bool(layer_properties, "visible")?,
+            opacity: nrbf.int(layer_properties, "opacity")?.clamp(0, 255) as u8,
+            blend_mode,
+            image: RgbaImage::from_raw(width, height, pixels).unwrap(),
+        });
+    }
+
+    Ok(PdnDocument {
+        width,
+        height,
+        layers,
+    })
+}
+
+pub fn load_pdn(path: impl AsRef<Path>) -> Result<PdnDocument, PdnError> {
+    read_pdn(&std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blending() {
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+
+        assert_eq!(BlendMode::Normal.blend(red, blue, 255), blue);
+        assert_eq!(BlendMode::Normal.blend(red, blue, 0), red);
+        assert_eq!(BlendMode::Normal.blend([0; 4], blue, 255), blue);
+        assert_eq!(BlendMode::Normal.blend(red, blue, 128), [127, 0, 128, 255]);
+        assert_eq!(
+            BlendMode::Additive.blend(red, blue, 255),
+            [255, 0, 255, 255]
+        );
+        assert_eq!(BlendMode::Multiply.blend(red, blue, 255), [0, 0, 0, 255]);
+        assert_eq!(BlendMode::Screen.blend(red, blue, 255), [255, 0, 255, 255]);
+        //Blend modes only apply where both layers have something
+        assert_eq!(BlendMode::Multiply.blend([0; 4], blue, 255), blue);
+
+        assert_eq!(
+            BlendMode::from_class("PaintDotNet.UserBlendOps+ColorDodgeBlendOp"),
+            Some(BlendMode::ColorDodge)
+        );
+    }
+
+    #[test]
+    fn moleman() {
+        let pdn = read_pdn(include_bytes!("../assets/moleman.pdn")).unwrap();
+
+        assert_eq!((pdn.width, pdn.height), (96, 32));
+        assert_eq!(pdn.layers.len(), 3);
+        assert_eq!(pdn.layers[0].name, "1: Background");
+        assert!(pdn.layer("4: Background").unwrap().visible);
+        assert!(pdn.layers.iter().all(|l| l.blend_mode == BlendMode::Normal));
+
+        //Matches what was exported by hand
+        let png = image::load_from_memory(include_bytes!("../assets/moleman.png")).unwrap();
+        assert!(pdn.flatten() == png.to_rgba8());
+    }
+
+    #[test]
+    fn bad_chunks() {
+        //Raw data in 4 byte chunks, the second of which claims to be chunk 2^32 - 1
+        let mut data = vec![1, 0, 0, 0, 4];
+        data.extend([0, 0, 0, 0, 0, 0, 0, 4, 1, 2, 3, 4]);
+        data.extend([0xff, 0xff, 0xff, 0xff, 0, 0, 0, 4, 5, 6, 7, 8]);
+
+        assert!(matches!(
+            layer_pixels(&data, &mut 0, 8),
+            Err(PdnError::Malformed(_))
+        ));
+
+        data[17..21].copy_from_slice(&[0, 0, 0, 1]);
+        assert_eq!(
+            layer_pixels(&data, &mut 0, 8).unwrap(),
+            [1, 2, 3, 4, 5, 6, 7, 8]
+        );
+
+        //A chunk that says it's bigger than the whole file
+        data[21..25].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        assert!(matches!(
+            layer_pixels(&data, &mut 0, 8),
+            Err(PdnError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn not_pdn() {
+        assert!(matches!(read_pdn(b"PNG"), Err(PdnError::NotPdn)));
+        assert!(matches!(
+            read_pdn(b"PDN3\x10\x00\x00<pdn"),
+            Err(PdnError::Malformed(_))
+        ));
+    }
+}
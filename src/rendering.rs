@@ -1,8 +1,192 @@
-use crate::engine;
+use std::cmp::Ordering;
+
+use crate::{
+    engine::{self, Engine},
+    mesh::Mesh,
+    sprite::{SpriteBatcher, SpriteInstance},
+    tilemap::TilemapPushConstants,
+};
 
 use bevy_ecs::prelude as ecs;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    pipeline::Pipeline,
+};
 
 #[derive(ecs::Component)]
 pub struct Renderer {
     pub material: engine::MatID,
 }
+
+///Layer for anything without a [`SortingLayer`]
+pub const DEFAULT_LAYER: &str = "Default";
+
+/// Which named layer of [`SortingLayers`] to draw an entity in
+#[derive(ecs::Component, Clone, Debug)]
+pub struct SortingLayer(pub String);
+
+/// Order to draw in within a layer, higher is drawn on top
+#[derive(ecs::Component, Clone, Copy, Debug, Default)]
+pub struct ZIndex(pub i32);
+
+#[derive(Clone, Debug)]
+pub struct LayerSettings {
+    pub name: String,
+    ///Draw entities lower down the screen on top, for a top-down look
+    pub y_sort: bool,
+}
+
+/// Resource listing the layers, from the back to the front
+#[derive(Clone, Debug)]
+pub struct SortingLayers(pub Vec<LayerSettings>);
+
+impl Default for SortingLayers {
+    fn default() -> Self {
+        Self::new(["Background", DEFAULT_LAYER, "Foreground"])
+    }
+}
+
+impl SortingLayers {
+    pub fn new<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        Self(
+            names
+                .into_iter()
+                .map(|name| LayerSettings {
+                    name: String::from(name),
+                    y_sort: false,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn set_y_sort(&mut self, name: &str, y_sort: bool) {
+        if let Some(layer) = self.0.iter_mut().find(|l| l.name == name) {
+            layer.y_sort = y_sort;
+        }
+    }
+
+    ///Index of the layer called `name`, or of [`DEFAULT_LAYER`] if there is no such layer
+    fn index(&self, name: Option<&str>) -> usize {
+        let find = |name| self.0.iter().position(|l| l.name == name);
+
+        name.and_then(find)
+            .or_else(|| find(DEFAULT_LAYER))
+            .unwrap_or(0)
+    }
+
+    ///Where to draw an entity at height `y`
+    pub fn key(&self, layer: Option<&SortingLayer>, z: Option<&ZIndex>, y: f32) -> SortKey {
+        let layer = self.index(layer.map(|l| l.0.as_str()));
+        let y_sort = self.0.get(layer).is_some_and(|l| l.y_sort);
+
+        SortKey {
+            layer,
+            z: z.copied().unwrap_or_default().0,
+            //Higher up is further away, so drawn first
+            depth: if y_sort { -y } else { 0.0 },
+        }
+    }
+}
+
+/// Drawables are recorded in increasing order of these
+#[derive(Clone, Copy, Debug)]
+pub struct SortKey {
+    layer: usize,
+    z: i32,
+    depth: f32,
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.layer, self.z)
+            .cmp(&(other.layer, other.z))
+            .then(self.depth.total_cmp(&other.depth))
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortKey {}
+
+/// Something to record into the frame's command buffer
+pub enum Drawable {
+    Tilemap {
+        material: engine::MatID,
+        push_constants: TilemapPushConstants,
+        instances: u32,
+    },
+    Sprite {
+        material: engine::MatID,
+        instance: SpriteInstance,
+    },
+}
+
+///Sort `drawables` and record them, batching sprites that end up next to each other
+///
+///Drawables with equal keys keep the order they were given in
+pub fn draw_sorted(
+    mut drawables: Vec<(SortKey, Drawable)>,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    engine: &Engine,
+    mesh: &dyn Mesh,
+    sprites: &mut SpriteBatcher,
+) {
+    drawables.sort_by_key(|(key, _)| *key);
+
+    for (_, drawable) in drawables {
+        match drawable {
+            Drawable::Tilemap {
+                material,
+                push_constants,
+                instances,
+            } => {
+                //Sprites sorted before the tilemap have to be drawn first
+                sprites.draw(builder, engine, mesh);
+
+                let e = engine.get_material(&material);
+                e.bind(builder, mesh);
+
+                builder.push_constants(e.pipeline.layout().clone(), 0, push_constants);
+
+                e.draw(builder, mesh, instances);
+            }
+            Drawable::Sprite { material, instance } => sprites.push(material, instance),
+        }
+    }
+
+    sprites.draw(builder, engine, mesh);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_keys() {
+        let mut layers = SortingLayers::default();
+        let background = SortingLayer(String::from("Background"));
+        let missing = SortingLayer(String::from("Missing"));
+
+        let default = layers.key(None, None, 0.0);
+        assert!(layers.key(Some(&background), Some(&ZIndex(10)), 0.0) < default);
+        assert_eq!(layers.key(Some(&missing), None, 0.0), default);
+        assert!(layers.key(None, Some(&ZIndex(1)), 0.0) > default);
+        //Without y-sorting height makes no difference
+        assert_eq!(layers.key(None, None, 5.0), default);
+
+        layers.set_y_sort(DEFAULT_LAYER, true);
+        assert!(layers.key(None, None, 5.0) < layers.key(None, None, 1.0));
+        //Z index still comes first
+        assert!(layers.key(None, Some(&ZIndex(1)), 5.0) > layers.key(None, None, 1.0));
+    }
+}
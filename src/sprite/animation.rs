@@ -0,0 +1,297 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    sync::Arc,
+};
+
+use bevy_ecs::prelude as ecs;
+use serde::{Deserialize, Serialize};
+
+use super::SpriteData;
+
+///What an animation does once it reaches its last frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoopMode {
+    ///Start again from the first frame
+    Loop,
+    ///Play backwards to the first frame, then forwards again
+    PingPong,
+    ///Stay on the last frame
+    Once,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    ///Coordinate of the tile to show on the sprite's sheet
+    #[serde(default)]
+    pub tile_x: usize,
+    #[serde(default)]
+    pub tile_y: usize,
+    ///Name of a frame in [`Sprite::frames`](super::Sprite::frames) to show instead of the tile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    ///How long to show it for, in seconds
+    pub duration: f32,
+}
+
+/// A clip made of frames from a single sprite sheet
+///
+/// ```json
+/// {
+///     "name": "run",
+///     "mode": "Loop",
+///     "frames": [
+///         { "tile_x": 1, "tile_y": 0, "duration": 0.15 },
+///         { "tile_x": 2, "tile_y": 0, "duration": 0.15 }
+///     ]
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Animation {
+    pub name: String,
+    pub mode: LoopMode,
+    pub frames: Vec<Frame>,
+}
+
+impl Animation {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let animation: Self = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+        if animation.frames.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("animation `{}` has no frames", animation.name),
+            ));
+        }
+
+        Ok(animation)
+    }
+
+    ///The order frames are shown in over one cycle of the animation
+    ///
+    ///Ping-pong doesn't repeat the first and last frames when it turns around
+    fn sequence(&self) -> Vec<usize> {
+        let mut sequence: Vec<usize> = (0..self.frames.len()).collect();
+
+        if self.mode == LoopMode::PingPong && self.frames.len() > 2 {
+            sequence.extend((1..self.frames.len() - 1).rev());
+        }
+
+        sequence
+    }
+
+    ///How long one cycle of the animation takes, in seconds
+    pub fn cycle_duration(&self) -> f32 {
+        self.sequence()
+            .into_iter()
+            .map(|i| self.frames[i].duration)
+            .sum()
+    }
+
+    ///Which frame to show `time` seconds after the animation started
+    pub fn frame_at(&self, time: f32) -> usize {
+        let cycle = self.cycle_duration();
+
+        if self.frames.is_empty() || cycle <= 0.0 {
+            return 0;
+        }
+
+        let mut time = match self.mode {
+            LoopMode::Once if time >= cycle => return self.frames.len() - 1,
+            LoopMode::Once => time,
+            LoopMode::Loop | LoopMode::PingPong => time.rem_euclid(cycle),
+        };
+
+        let sequence = self.sequence();
+        for &i in &sequence {
+            if time < self.frames[i].duration {
+                return i;
+            }
+            time -= self.frames[i].duration;
+        }
+
+        //Only reachable through rounding at the very end of a cycle
+        *sequence.last().unwrap()
+    }
+}
+
+///Sent when a [`LoopMode::Once`] animation reaches its end, or a looping one finishes a cycle
+#[derive(Debug, Clone)]
+pub struct AnimationFinished {
+    pub entity: ecs::Entity,
+    ///[`Animation::name`] of the clip that finished
+    pub clip: String,
+}
+
+/// Plays an [`Animation`] on the entity's [`SpriteData`]
+#[derive(ecs::Component)]
+pub struct Animator {
+    clip: Arc<Animation>,
+    ///Seconds since the clip started
+    time: f32,
+    ///Multiplier on how fast time passes for the clip
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl Animator {
+    pub fn new(clip: Arc<Animation>) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn clip(&self) -> &Arc<Animation> {
+        &self.clip
+    }
+
+    ///Switch to another clip, starting from its first frame
+    ///
+    ///Playing the clip that is already playing does nothing, so this can be called every update
+    pub fn play(&mut self, clip: &Arc<Animation>) {
+        if !Arc::ptr_eq(&self.clip, clip) {
+            self.clip = clip.clone();
+            self.time = 0.0;
+        }
+    }
+
+    ///If a [`LoopMode::Once`] clip has reached its end
+    pub fn is_finished(&self) -> bool {
+        self.clip.mode == LoopMode::Once && self.time >= self.clip.cycle_duration()
+    }
+
+    pub fn current_frame(&self) -> &Frame {
+        &self.clip.frames[self.clip.frame_at(self.time)]
+    }
+
+    ///Move the animation on by `dt` seconds
+    ///
+    ///## Returns
+    ///How many times the clip finished during that time
+    pub fn advance(&mut self, dt: f32) -> usize {
+        let cycle = self.clip.cycle_duration();
+
+        if self.paused || cycle <= 0.0 || self.is_finished() {
+            return 0;
+        }
+
+        let before = self.time;
+        self.time += dt * self.speed;
+
+        match self.clip.mode {
+            LoopMode::Once => {
+                if self.time >= cycle {
+                    self.time = cycle;
+                    1
+                } else {
+                    0
+                }
+            }
+            LoopMode::Loop | LoopMode::PingPong => {
+                let finished = (self.time / cycle).floor() - (before / cycle).floor();
+                //Keep time small, so it doesn't lose precision after a long time running
+                self.time = self.time.rem_euclid(cycle);
+
+                finished.max(0.0) as usize
+            }
+        }
+    }
+}
+
+///Advance every [`Animator`] and show its current frame
+pub fn animation_on_update(
+    time: ecs::Res<crate::Time>,
+    mut query: ecs::Query<(ecs::Entity, &mut Animator, &mut SpriteData)>,
+    mut finished: ecs::EventWriter<AnimationFinished>,
+) {
+    query.for_each_mut(|(entity, mut animator, mut sprite)| {
+        for _ in 0..animator.advance(time.dt) {
+            finished.send(AnimationFinished {
+                entity,
+                clip: animator.clip.name.clone(),
+            });
+        }
+
+        let frame = animator.current_frame();
+        sprite.tile_x = frame.tile_x;
+        sprite.tile_y = frame.tile_y;
+
+        if sprite.frame != frame.name {
+            sprite.frame = frame.name.clone();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(mode: LoopMode, durations: &[f32]) -> Arc<Animation> {
+        Arc::new(Animation {
+            name: String::from("test"),
+            mode,
+            frames: durations
+                .iter()
+                .enumerate()
+                .map(|(i, &duration)| Frame {
+                    tile_x: i,
+                    tile_y: 0,
+                    name: None,
+                    duration,
+                })
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn frames() {
+        let once = clip(LoopMode::Once, &[1.0, 2.0, 1.0]);
+        assert_eq!(once.frame_at(0.5), 0);
+        assert_eq!(once.frame_at(2.5), 1);
+        assert_eq!(once.frame_at(3.5), 2);
+        assert_eq!(once.frame_at(10.0), 2);
+
+        let looping = clip(LoopMode::Loop, &[1.0, 2.0, 1.0]);
+        assert_eq!(looping.cycle_duration(), 4.0);
+        assert_eq!(looping.frame_at(4.5), 0);
+        assert_eq!(looping.frame_at(6.5), 1);
+
+        let ping_pong = clip(LoopMode::PingPong, &[1.0, 1.0, 1.0]);
+        assert_eq!(ping_pong.cycle_duration(), 4.0);
+        let shown: Vec<usize> = (0..8).map(|t| ping_pong.frame_at(t as f32 + 0.5)).collect();
+        assert_eq!(shown, [0, 1, 2, 1, 0, 1, 2, 1]);
+    }
+
+    #[test]
+    fn animator() {
+        let mut a = Animator::new(clip(LoopMode::Loop, &[0.5, 0.5]));
+
+        assert_eq!(a.advance(0.75), 0);
+        assert_eq!(a.current_frame().tile_x, 1);
+        assert_eq!(a.advance(0.5), 1);
+        assert_eq!(a.current_frame().tile_x, 0);
+        assert_eq!(a.advance(2.0), 2);
+
+        a.paused = true;
+        assert_eq!(a.advance(5.0), 0);
+
+        let once = clip(LoopMode::Once, &[0.5, 0.5]);
+        a.play(&once);
+        a.paused = false;
+        assert_eq!(a.current_frame().tile_x, 0);
+        assert_eq!(a.advance(0.9), 0);
+        assert_eq!(a.advance(0.2), 1);
+        assert!(a.is_finished());
+        //Only finishes once
+        assert_eq!(a.advance(1.0), 0);
+        assert_eq!(a.current_frame().tile_x, 1);
+
+        //Playing the same clip again doesn't restart it
+        a.play(&once);
+        assert!(a.is_finished());
+    }
+}
@@ -0,0 +1,499 @@
+use std::{fmt, io, path::Path};
+
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use super::{
+    animation::{Frame, LoopMode},
+    Animation, Sprite, SpriteFrame,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+#[derive(Deserialize)]
+struct Size {
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawFrame {
+    frame: Rect,
+    #[serde(default)]
+    rotated: bool,
+    ///Where the frame sits on the canvas, which only differs from the canvas when trimmed
+    sprite_source_size: Rect,
+    ///Milliseconds
+    duration: u32,
+}
+
+#[derive(Deserialize)]
+struct RawArrayFrame {
+    filename: String,
+    #[serde(flatten)]
+    frame: RawFrame,
+}
+
+///Frames from the hash layout, kept in the order they were written rather than sorted by name
+struct OrderedFrames(Vec<(String, RawFrame)>);
+
+impl<'de> Deserialize<'de> for OrderedFrames {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct OrderedFramesVisitor;
+
+        impl<'de> Visitor<'de> for OrderedFramesVisitor {
+            type Value = OrderedFrames;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of frame names to frames")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut frames = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    frames.push(entry);
+                }
+
+                Ok(OrderedFrames(frames))
+            }
+        }
+
+        deserializer.deserialize_map(OrderedFramesVisitor)
+    }
+}
+
+///Aseprite can export frames as an array or as a hash, depending on the "JSON Data" setting
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawFrames {
+    Array(Vec<RawArrayFrame>),
+    Hash(OrderedFrames),
+}
+
+#[derive(Deserialize)]
+struct RawTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: Option<String>,
+    ///Newer versions write how many times to play the tag, with `"0"` or no value meaning forever
+    #[serde(default)]
+    repeat: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawSliceKey {
+    frame: usize,
+    bounds: Rect,
+    #[serde(default)]
+    pivot: Option<Point>,
+}
+
+#[derive(Deserialize)]
+struct RawSlice {
+    name: String,
+    keys: Vec<RawSliceKey>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawMeta {
+    #[serde(default)]
+    image: String,
+    size: Size,
+    #[serde(default)]
+    frame_tags: Vec<RawTag>,
+    #[serde(default)]
+    slices: Vec<RawSlice>,
+}
+
+#[derive(Deserialize)]
+struct RawSheet {
+    frames: RawFrames,
+    meta: RawMeta,
+}
+
+/// A slice key, which applies from its frame until the next key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SliceKey {
+    pub frame: usize,
+    ///On the canvas, in pixels from the top left
+    pub bounds: Rect,
+    ///Relative to the top left of `bounds`
+    pub pivot: Option<[i32; 2]>,
+}
+
+/// A named region drawn in Aseprite, like a hitbox or the point a character stands on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slice {
+    pub name: String,
+    pub keys: Vec<SliceKey>,
+}
+
+impl Slice {
+    ///The key that applies to `frame`, if the slice has started by then
+    pub fn key_at(&self, frame: usize) -> Option<&SliceKey> {
+        self.keys
+            .iter()
+            .filter(|k| k.frame <= frame)
+            .max_by_key(|k| k.frame)
+    }
+}
+
+/// Everything from an Aseprite sheet export
+pub struct AsepriteSheet {
+    ///The sheet image, as written by Aseprite relative to the JSON file
+    pub image: String,
+    ///Frames are named after the exported filenames, and use pivots from any slice with one
+    pub sprite: Sprite,
+    ///Names of the sprite's frames, in timeline order
+    pub frames: Vec<String>,
+    ///One clip per tag, or a single `default` clip of the whole timeline if there are no tags
+    pub clips: Vec<Animation>,
+    pub slices: Vec<Slice>,
+}
+
+impl AsepriteSheet {
+    pub fn clip(&self, name: &str) -> Option<&Animation> {
+        self.clips.iter().find(|c| c.name == name)
+    }
+}
+
+#[derive(Debug)]
+pub enum AsepriteError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    ///Parsed, but refers to things that aren't there or that we can't draw
+    Invalid(String),
+}
+
+impl fmt::Display for AsepriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsepriteError::Io(e) => write!(f, "{}", e),
+            AsepriteError::Json(e) => write!(f, "bad aseprite sheet: {}", e),
+            AsepriteError::Invalid(e) => write!(f, "bad aseprite sheet: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AsepriteError {}
+
+impl From<io::Error> for AsepriteError {
+    fn from(e: io::Error) -> Self {
+        AsepriteError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for AsepriteError {
+    fn from(e: serde_json::Error) -> Self {
+        AsepriteError::Json(e)
+    }
+}
+
+pub fn import_aseprite(path: impl AsRef<Path>) -> Result<AsepriteSheet, AsepriteError> {
+    parse_aseprite(&std::fs::read_to_string(path)?)
+}
+
+///Read the JSON written by Aseprite's "Export Sprite Sheet", in either layout
+pub fn parse_aseprite(json: &str) -> Result<AsepriteSheet, AsepriteError> {
+    let raw: RawSheet = serde_json::from_str(json)?;
+    let meta = raw.meta;
+
+    let frames = match raw.frames {
+        RawFrames::Array(frames) => frames.into_iter().map(|f| (f.filename, f.frame)).collect(),
+        RawFrames::Hash(OrderedFrames(frames)) => frames,
+    };
+
+    let slices: Vec<Slice> = meta
+        .slices
+        .into_iter()
+        .map(|s| Slice {
+            name: s.name,
+            keys: s
+                .keys
+                .into_iter()
+                .map(|k| SliceKey {
+                    frame: k.frame,
+                    bounds: k.bounds,
+                    pivot: k.pivot.map(|p| [p.x, p.y]),
+                })
+                .collect(),
+        })
+        .collect();
+
+    let mut sprite = Sprite::new(1, 1, meta.size.w as usize, meta.size.h as usize);
+    let (sheet_w, sheet_h) = (meta.size.w as f32, meta.size.h as f32);
+
+    let mut names = Vec::with_capacity(frames.len());
+    let mut durations = Vec::with_capacity(frames.len());
+
+    for (i, (name, f)) in frames.into_iter().enumerate() {
+        if f.rotated {
+            return Err(AsepriteError::Invalid(format!(
+                "frame `{}` is rotated, which isn't supported",
+                name
+            )));
+        }
+
+        let r = f.frame;
+
+        //Pivots are on the canvas, but our pivots go from the bottom left of the frame
+        let pivot = slices.iter().filter_map(|s| s.key_at(i)).find_map(|k| {
+            let [px, py] = k.pivot?;
+            let (x, y) = (k.bounds.x + px, k.bounds.y + py);
+            let source = f.sprite_source_size;
+
+            Some([
+                (x - source.x) as f32 / source.w.max(1) as f32,
+                1.0 - (y - source.y) as f32 / source.h.max(1) as f32,
+            ])
+        });
+
+        let frame = SpriteFrame {
+            uv_min: [r.x as f32 / sheet_w, r.y as f32 / sheet_h],
+            uv_max: [(r.x + r.w) as f32 / sheet_w, (r.y + r.h) as f32 / sheet_h],
+            width: r.w as u32,
+            height: r.h as u32,
+            pivot,
+        };
+
+        if sprite.frames.insert(name.clone(), frame).is_some() {
+            return Err(AsepriteError::Invalid(format!(
+                "more than one frame is called `{}`",
+                name
+            )));
+        }
+
+        names.push(name);
+        durations.push(f.duration as f32 / 1000.0);
+    }
+
+    let clip_frames = |indices: &mut dyn Iterator<Item = usize>| -> Vec<Frame> {
+        indices
+            .map(|i| Frame {
+                tile_x: 0,
+                tile_y: 0,
+                name: Some(names[i].clone()),
+                duration: durations[i],
+            })
+            .collect()
+    };
+
+    let mut clips = Vec::new();
+
+    for tag in &meta.frame_tags {
+        if tag.from > tag.to || tag.to >= names.len() {
+            return Err(AsepriteError::Invalid(format!(
+                "tag `{}` covers frames {} to {}, but there are {} frames",
+                tag.name,
+                tag.from,
+                tag.to,
+                names.len()
+            )));
+        }
+
+        let range = tag.from..=tag.to;
+        let (frames, ping_pong) = match tag.direction.as_deref().unwrap_or("forward") {
+            "forward" => (clip_frames(&mut range.into_iter()), false),
+            "reverse" => (clip_frames(&mut range.rev()), false),
+            "pingpong" => (clip_frames(&mut range.into_iter()), true),
+            "pingpong_reverse" => (clip_frames(&mut range.rev()), true),
+            other => {
+                return Err(AsepriteError::Invalid(format!(
+                    "tag `{}` has unknown direction `{}`",
+                    tag.name, other
+                )))
+            }
+        };
+
+        //Animations either loop forever or play once, so other counts can't be shown properly
+        let once = match tag.repeat.as_deref().map(str::parse::<u32>) {
+            None | Some(Ok(0)) => false,
+            Some(Ok(1)) => true,
+            _ => {
+                return Err(AsepriteError::Invalid(format!(
+                    "tag `{}` repeats {} times, only forever or once are supported",
+                    tag.name,
+                    tag.repeat.as_deref().unwrap_or_default()
+                )))
+            }
+        };
+
+        clips.push(Animation {
+            name: tag.name.clone(),
+            mode: match (ping_pong, once) {
+                (_, true) => LoopMode::Once,
+                (true, false) => LoopMode::PingPong,
+                (false, false) => LoopMode::Loop,
+            },
+            frames,
+        });
+    }
+
+    if clips.is_empty() && !names.is_empty() {
+        clips.push(Animation {
+            name: String::from("default"),
+            mode: LoopMode::Loop,
+            frames: clip_frames(&mut (0..names.len())),
+        });
+    }
+
+    Ok(AsepriteSheet {
+        image: meta.image,
+        sprite,
+        frames: names,
+        clips,
+        slices,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = r##"{
+        "frames": {
+            "mole 0.aseprite": {
+                "frame": { "x": 0, "y": 0, "w": 32, "h": 32 },
+                "rotated": false,
+                "trimmed": false,
+                "spriteSourceSize": { "x": 0, "y": 0, "w": 32, "h": 32 },
+                "sourceSize": { "w": 32, "h": 32 },
+                "duration": 100
+            },
+            "mole 10.aseprite": {
+                "frame": { "x": 32, "y": 0, "w": 32, "h": 32 },
+                "rotated": false,
+                "trimmed": false,
+                "spriteSourceSize": { "x": 0, "y": 0, "w": 32, "h": 32 },
+                "sourceSize": { "w": 32, "h": 32 },
+                "duration": 150
+            },
+            "mole 2.aseprite": {
+                "frame": { "x": 64, "y": 0, "w": 32, "h": 32 },
+                "rotated": false,
+                "trimmed": false,
+                "spriteSourceSize": { "x": 0, "y": 0, "w": 32, "h": 32 },
+                "sourceSize": { "w": 32, "h": 32 },
+                "duration": 150
+            }
+        },
+        "meta": {
+            "app": "https://www.aseprite.org/",
+            "image": "mole.png",
+            "format": "RGBA8888",
+            "size": { "w": 96, "h": 32 },
+            "scale": "1",
+            "frameTags": [
+                { "name": "run", "from": 1, "to": 2, "direction": "pingpong" },
+                { "name": "dig", "from": 0, "to": 1, "direction": "reverse", "repeat": "1" }
+            ],
+            "layers": [],
+            "slices": [
+                { "name": "feet", "color": "#0000ffff", "keys": [
+                    { "frame": 0, "bounds": { "x": 8, "y": 24, "w": 16, "h": 8 }, "pivot": { "x": 8, "y": 8 } }
+                ] },
+                { "name": "hitbox", "color": "#ff0000ff", "keys": [
+                    { "frame": 0, "bounds": { "x": 4, "y": 4, "w": 24, "h": 28 } },
+                    { "frame": 2, "bounds": { "x": 6, "y": 4, "w": 20, "h": 28 } }
+                ] }
+            ]
+        }
+    }"##;
+
+    #[test]
+    fn hash_layout() {
+        let sheet = parse_aseprite(HASH).unwrap();
+
+        assert_eq!(sheet.image, "mole.png");
+        //Kept in file order, even though "10" sorts before "2"
+        assert_eq!(
+            sheet.frames,
+            ["mole 0.aseprite", "mole 10.aseprite", "mole 2.aseprite"]
+        );
+
+        let f = sheet.sprite.frame("mole 10.aseprite").unwrap();
+        assert_eq!(f.uv_min, [1.0 / 3.0, 0.0]);
+        assert_eq!((f.width, f.height), (32, 32));
+        //The feet pivot is 16 across and right at the bottom
+        assert_eq!(f.pivot, Some([0.5, 0.0]));
+
+        let run = sheet.clip("run").unwrap();
+        assert_eq!(run.mode, LoopMode::PingPong);
+        assert_eq!(run.frames[0].name.as_deref(), Some("mole 10.aseprite"));
+        assert_eq!(run.frames[0].duration, 0.15);
+
+        let dig = sheet.clip("dig").unwrap();
+        assert_eq!(dig.mode, LoopMode::Once);
+        assert_eq!(dig.frames[0].name.as_deref(), Some("mole 10.aseprite"));
+
+        let hitbox = &sheet.slices[1];
+        assert_eq!(hitbox.key_at(1).unwrap().bounds.x, 4);
+        assert_eq!(hitbox.key_at(2).unwrap().bounds.x, 6);
+    }
+
+    #[test]
+    fn array_layout() {
+        let sheet = parse_aseprite(
+            r#"{
+                "frames": [
+                    { "filename": "a", "frame": { "x": 0, "y": 0, "w": 16, "h": 16 },
+                      "spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 16 }, "duration": 200 },
+                    { "filename": "b", "frame": { "x": 16, "y": 0, "w": 16, "h": 16 },
+                      "spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 16 }, "duration": 100 }
+                ],
+                "meta": { "size": { "w": 32, "h": 16 } }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(sheet.frames, ["a", "b"]);
+        assert_eq!(sheet.sprite.frame("b").unwrap().pivot, None);
+
+        let default = sheet.clip("default").unwrap();
+        assert_eq!(default.mode, LoopMode::Loop);
+        assert!((default.cycle_duration() - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bad_tags() {
+        let json = HASH.replace(r#""to": 2, "direction": "pingpong""#, r#""to": 3"#);
+        assert!(matches!(
+            parse_aseprite(&json),
+            Err(AsepriteError::Invalid(_))
+        ));
+
+        let json = HASH.replace("\"pingpong\"", "\"sideways\"");
+        assert!(matches!(
+            parse_aseprite(&json),
+            Err(AsepriteError::Invalid(_))
+        ));
+
+        //Playing three times would otherwise quietly play once
+        let json = HASH.replace(r#""repeat": "1""#, r#""repeat": "3""#);
+        assert!(matches!(
+            parse_aseprite(&json),
+            Err(AsepriteError::Invalid(_))
+        ));
+    }
+}
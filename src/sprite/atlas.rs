@@ -0,0 +1,350 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use super::{Sprite, SpriteFrame};
+use crate::texture::open_image;
+
+///The biggest atlas we will make, which every GPU we care about can handle
+pub const MAX_ATLAS_SIZE: u32 = 4096;
+
+/// A frame packed into an atlas
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AtlasFrame {
+    ///Top left of the image in pixels, not counting the gutter around it
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+}
+
+/// Written next to an atlas image, saying where each frame ended up
+///
+/// ```json
+/// {
+///     "width": 128,
+///     "height": 64,
+///     "padding": 1,
+///     "frames": {
+///         "Mole-man running 1": { "x": 1, "y": 1, "width": 32, "height": 32, ... }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AtlasManifest {
+    pub width: u32,
+    pub height: u32,
+    ///Pixels of gutter around each frame
+    pub padding: u32,
+    pub frames: BTreeMap<String, AtlasFrame>,
+}
+
+impl AtlasManifest {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AtlasError> {
+        let file = File::open(path)?;
+
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), AtlasError> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    ///A sprite drawing from the atlas, with a frame for everything in it
+    ///
+    ///The whole atlas is a single tile, so frames have to be picked by name
+    pub fn sprite(&self) -> Sprite {
+        let mut sprite = Sprite::new(1, 1, self.width as usize, self.height as usize);
+
+        sprite.frames = self
+            .frames
+            .iter()
+            .map(|(name, f)| {
+                (
+                    name.clone(),
+                    SpriteFrame {
+                        uv_min: f.uv_min,
+                        uv_max: f.uv_max,
+                        width: f.width,
+                        height: f.height,
+                        pivot: None,
+                    },
+                )
+            })
+            .collect();
+
+        sprite
+    }
+}
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Io(io::Error),
+    Image(image::ImageError),
+    Manifest(serde_json::Error),
+    ///Two images would have the same frame name
+    DuplicateName(String),
+    ///The images don't fit in a [`MAX_ATLAS_SIZE`] square
+    TooBig,
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtlasError::Io(e) => write!(f, "{}", e),
+            AtlasError::Image(e) => write!(f, "bad image: {}", e),
+            AtlasError::Manifest(e) => write!(f, "bad manifest: {}", e),
+            AtlasError::DuplicateName(name) => write!(f, "more than one frame is called {}", name),
+            AtlasError::TooBig => write!(f, "frames don't fit in a {0}x{0} atlas", MAX_ATLAS_SIZE),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+impl From<io::Error> for AtlasError {
+    fn from(e: io::Error) -> Self {
+        AtlasError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for AtlasError {
+    fn from(e: serde_json::Error) -> Self {
+        AtlasError::Manifest(e)
+    }
+}
+
+impl From<image::ImageError> for AtlasError {
+    fn from(e: image::ImageError) -> Self {
+        AtlasError::Image(e)
+    }
+}
+
+///Place rectangles of `sizes` without overlapping, in rows ordered by height
+///
+///The atlas width is doubled until everything fits with neither side bigger than `max_size`
+///
+///## Returns
+///The atlas size, and the top left of each rectangle in the same order as `sizes`
+pub fn pack(sizes: &[[u32; 2]], max_size: u32) -> Option<([u32; 2], Vec<[u32; 2]>)> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse((sizes[i][1], sizes[i][0])));
+
+    let area: u64 = sizes.iter().map(|[w, h]| *w as u64 * *h as u64).sum();
+    let widest = sizes.iter().map(|s| s[0]).max().unwrap_or(1);
+
+    let mut width = ((area as f64).sqrt().ceil() as u32)
+        .max(widest)
+        .max(1)
+        .next_power_of_two();
+
+    while width <= max_size {
+        let mut positions = vec![[0, 0]; sizes.len()];
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+
+        for &i in &order {
+            let [w, h] = sizes[i];
+
+            if x + w > width {
+                x = 0;
+                y += row_height;
+                row_height = 0;
+            }
+
+            positions[i] = [x, y];
+            x += w;
+            row_height = row_height.max(h);
+        }
+
+        let height = (y + row_height).max(1).next_power_of_two();
+        if height <= max_size {
+            return Some(([width, height], positions));
+        }
+
+        width *= 2;
+    }
+
+    None
+}
+
+///Pack `images` into a single atlas, with `padding` pixels of gutter around each one
+///
+///The gutter repeats the edge pixels of its frame, so filtering never picks up a neighbour
+pub fn build_atlas(
+    images: &[(String, RgbaImage)],
+    padding: u32,
+) -> Result<(RgbaImage, AtlasManifest), AtlasError> {
+    let mut frames = BTreeMap::new();
+
+    let sizes: Vec<[u32; 2]> = images
+        .iter()
+        .map(|(_, i)| [i.width() + padding * 2, i.height() + padding * 2])
+        .collect();
+
+    let ([width, height], positions) = pack(&sizes, MAX_ATLAS_SIZE).ok_or(AtlasError::TooBig)?;
+
+    let mut atlas = RgbaImage::new(width, height);
+
+    for ((name, image), [x, y]) in images.iter().zip(positions) {
+        let (w, h) = (image.width(), image.height());
+
+        //Clamping to the image's edge extends it out into the gutter
+        if w > 0 && h > 0 {
+            for gy in 0..h + padding * 2 {
+                for gx in 0..w + padding * 2 {
+                    let px = gx.saturating_sub(padding).min(w - 1);
+                    let py = gy.saturating_sub(padding).min(h - 1);
+
+                    atlas.put_pixel(x + gx, y + gy, *image.get_pixel(px, py));
+                }
+            }
+        }
+
+        let (fx, fy) = (x + padding, y + padding);
+        let frame = AtlasFrame {
+            x: fx,
+            y: fy,
+            width: w,
+            height: h,
+            uv_min: [fx as f32 / width as f32, fy as f32 / height as f32],
+            uv_max: [
+                (fx + w) as f32 / width as f32,
+                (fy + h) as f32 / height as f32,
+            ],
+        };
+
+        if frames.insert(name.clone(), frame).is_some() {
+            return Err(AtlasError::DuplicateName(name.clone()));
+        }
+    }
+
+    Ok((
+        atlas,
+        AtlasManifest {
+            width,
+            height,
+            padding,
+            frames,
+        },
+    ))
+}
+
+///Where the manifest for the atlas image at `atlas` goes
+pub fn manifest_path(atlas: impl AsRef<Path>) -> PathBuf {
+    let mut path = atlas.as_ref().as_os_str().to_owned();
+    path.push(".atlas.json");
+
+    path.into()
+}
+
+///Pack image files, including `.pdn`s, into an atlas at `out`, naming each frame after its file, and write the
+///manifest next to it
+pub fn build_atlas_files(
+    inputs: &[impl AsRef<Path>],
+    out: impl AsRef<Path>,
+    padding: u32,
+) -> Result<AtlasManifest, AtlasError> {
+    let images = inputs
+        .iter()
+        .map(|path| {
+            let path = path.as_ref();
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            Ok((name, open_image(path)?))
+        })
+        .collect::<Result<Vec<_>, AtlasError>>()?;
+
+    let (atlas, manifest) = build_atlas(&images, padding)?;
+
+    atlas.save(&out)?;
+    manifest.save(manifest_path(&out))?;
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn overlaps(a: ([u32; 2], [u32; 2]), b: ([u32; 2], [u32; 2])) -> bool {
+        let ([ax, ay], [aw, ah]) = a;
+        let ([bx, by], [bw, bh]) = b;
+
+        ax < bx + bw && bx < ax + aw && ay < by + bh && by < ay + ah
+    }
+
+    #[test]
+    fn packs_without_overlap() {
+        let sizes = [[32, 32], [10, 40], [64, 8], [5, 5], [32, 32], [17, 3]];
+        let ([width, height], positions) = pack(&sizes, MAX_ATLAS_SIZE).unwrap();
+
+        for (i, (&p, &s)) in positions.iter().zip(&sizes).enumerate() {
+            assert!(p[0] + s[0] <= width && p[1] + s[1] <= height);
+
+            for (&q, &t) in positions.iter().zip(&sizes).skip(i + 1) {
+                assert!(!overlaps((p, s), (q, t)));
+            }
+        }
+
+        assert_eq!(pack(&[[100, 100]], 64), None);
+    }
+
+    #[test]
+    fn gutters() {
+        let red = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        let mut blue = RgbaImage::from_pixel(3, 1, Rgba([0, 0, 255, 255]));
+        blue.put_pixel(2, 0, Rgba([0, 255, 0, 255]));
+
+        let (atlas, manifest) = build_atlas(
+            &[(String::from("red"), red), (String::from("blue"), blue)],
+            1,
+        )
+        .unwrap();
+
+        let b = manifest.frames["blue"];
+        assert_eq!((b.width, b.height), (3, 1));
+        assert_eq!(atlas.get_pixel(b.x, b.y), &Rgba([0, 0, 255, 255]));
+        //The edge pixel is repeated out into the gutter
+        assert_eq!(atlas.get_pixel(b.x + 3, b.y), &Rgba([0, 255, 0, 255]));
+        assert_eq!(atlas.get_pixel(b.x + 3, b.y + 1), &Rgba([0, 255, 0, 255]));
+
+        let r = manifest.frames["red"];
+        assert_eq!(
+            r.uv_min,
+            [
+                r.x as f32 / manifest.width as f32,
+                r.y as f32 / manifest.height as f32
+            ]
+        );
+
+        let sprite = manifest.sprite();
+        assert_eq!(sprite.frame("red").unwrap().uv_max, r.uv_max);
+        assert!(sprite.frame("green").is_none());
+
+        assert!(matches!(
+            build_atlas(
+                &[
+                    (String::from("a"), RgbaImage::new(1, 1)),
+                    (String::from("a"), RgbaImage::new(1, 1))
+                ],
+                0
+            ),
+            Err(AtlasError::DuplicateName(_))
+        ));
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{BufferUsage, CpuBufferPool},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::Device,
+    pipeline::{Pipeline, PipelineBindPoint},
+};
+
+use crate::{
+    engine::{Engine, MatID},
+    mesh::Mesh,
+};
+
+use super::SpriteInstance;
+
+///Descriptor set the instance buffer is bound to, after the material's own at 0
+const INSTANCE_SET: u32 = 1;
+
+/// Gathers the sprites drawn each frame, so runs of sprites sharing a material are drawn with one
+/// instanced call
+///
+/// The sprite vertex shader reads its [`SpriteInstance`] with `gl_InstanceIndex`
+pub struct SpriteBatcher {
+    pool: CpuBufferPool<SpriteInstance>,
+    ///In draw order, a new batch starts whenever the material changes
+    batches: Vec<(MatID, Vec<SpriteInstance>)>,
+}
+
+impl SpriteBatcher {
+    pub fn new(device: Arc<Device>) -> Self {
+        Self {
+            pool: CpuBufferPool::new(device, BufferUsage::storage_buffer()),
+            batches: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, material: MatID, instance: SpriteInstance) {
+        match self.batches.last_mut() {
+            Some((m, instances)) if *m == material => instances.push(instance),
+            _ => self.batches.push((material, vec![instance])),
+        }
+    }
+
+    ///Draw everything pushed since the last call, then start again
+    pub fn draw(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        engine: &Engine,
+        mesh: &dyn Mesh,
+    ) {
+        for (material, instances) in self.batches.drain(..) {
+            let e = engine.get_material(&material);
+            e.bind(builder, mesh);
+
+            let buffer = self
+                .pool
+                .chunk(instances.iter().copied())
+                .expect("failed to allocate sprite instances");
+
+            let set = PersistentDescriptorSet::new(
+                e.pipeline.layout().set_layouts()[INSTANCE_SET as usize].clone(),
+                [WriteDescriptorSet::buffer(0, buffer)],
+            )
+            .unwrap();
+
+            builder.bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                e.pipeline.layout().clone(),
+                INSTANCE_SET,
+                set,
+            );
+
+            e.draw(builder, mesh, instances.len() as u32);
+        }
+    }
+}
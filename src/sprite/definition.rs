@@ -0,0 +1,214 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use vulkano::image::StorageImage;
+
+use crate::{engine::Engine, texture::Texture};
+
+use super::Sprite;
+
+fn default_pixels_per_unit() -> f32 {
+    8.0
+}
+
+/// How a sprite sheet is laid out, stored next to the image as `<image>.sprite.json`
+///
+/// ```json
+/// {
+///     "grid_width": 3,
+///     "grid_height": 1,
+///     "tile_width": 32,
+///     "tile_height": 32,
+///     "pixels_per_unit": 8.0,
+///     "pivot": [0.5, 0.0],
+///     "frames": { "idle": [0, 0], "run 1": [1, 0] }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpriteDefinition {
+    pub grid_width: usize,
+    pub grid_height: usize,
+    pub tile_width: usize,
+    pub tile_height: usize,
+    #[serde(default = "default_pixels_per_unit")]
+    pub pixels_per_unit: f32,
+    ///See [`Sprite::pivot`]
+    #[serde(default)]
+    pub pivot: [f32; 2],
+    ///Names for tiles of the grid
+    #[serde(default)]
+    pub frames: BTreeMap<String, [usize; 2]>,
+}
+
+impl SpriteDefinition {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SpriteError> {
+        let file = File::open(path)?;
+        let definition: Self = serde_json::from_reader(BufReader::new(file))?;
+
+        definition.validate()?;
+
+        Ok(definition)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SpriteError> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), SpriteError> {
+        if self.grid_width == 0 || self.grid_height == 0 {
+            return Err(SpriteError::Invalid(String::from("the grid is empty")));
+        }
+        if self.pixels_per_unit <= 0.0 {
+            return Err(SpriteError::Invalid(format!(
+                "pixels_per_unit must be above 0, not {}",
+                self.pixels_per_unit
+            )));
+        }
+
+        for (name, [x, y]) in &self.frames {
+            if *x >= self.grid_width || *y >= self.grid_height {
+                return Err(SpriteError::Invalid(format!(
+                    "frame `{}` at {},{} is outside the {}x{} grid",
+                    name, x, y, self.grid_width, self.grid_height
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn sprite(&self) -> Sprite {
+        let mut sprite = Sprite::new(
+            self.grid_width,
+            self.grid_height,
+            self.tile_width,
+            self.tile_height,
+        );
+
+        sprite.pixels_per_unit = self.pixels_per_unit;
+        sprite.pivot = self.pivot;
+
+        for (name, &[x, y]) in &self.frames {
+            let frame = sprite.tile_frame(x, y);
+            sprite.frames.insert(name.clone(), frame);
+        }
+
+        sprite
+    }
+}
+
+#[derive(Debug)]
+pub enum SpriteError {
+    Io(io::Error),
+    Definition(serde_json::Error),
+    Image(image::ImageError),
+    ///The definition parsed, but doesn't make sense
+    Invalid(String),
+}
+
+impl fmt::Display for SpriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpriteError::Io(e) => write!(f, "{}", e),
+            SpriteError::Definition(e) => write!(f, "bad sprite definition: {}", e),
+            SpriteError::Image(e) => write!(f, "bad image: {}", e),
+            SpriteError::Invalid(e) => write!(f, "bad sprite definition: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SpriteError {}
+
+impl From<io::Error> for SpriteError {
+    fn from(e: io::Error) -> Self {
+        SpriteError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SpriteError {
+    fn from(e: serde_json::Error) -> Self {
+        SpriteError::Definition(e)
+    }
+}
+
+impl From<image::ImageError> for SpriteError {
+    fn from(e: image::ImageError) -> Self {
+        SpriteError::Image(e)
+    }
+}
+
+///Where the definition for the sheet at `image` goes
+pub fn definition_path(image: impl AsRef<Path>) -> PathBuf {
+    let mut path = image.as_ref().as_os_str().to_owned();
+    path.push(".sprite.json");
+
+    path.into()
+}
+
+///Load a sheet along with the definition next to it
+pub fn load_sprite(
+    image: &str,
+    engine: &Engine,
+) -> Result<(Arc<Sprite>, Texture<StorageImage>), SpriteError> {
+    let definition = SpriteDefinition::load(definition_path(image))?;
+    let texture = Texture::try_load(image, engine)?;
+
+    Ok((Arc::new(definition.sprite()), texture))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Result<SpriteDefinition, SpriteError> {
+        let definition: SpriteDefinition = serde_json::from_str(json)?;
+        definition.validate()?;
+
+        Ok(definition)
+    }
+
+    #[test]
+    fn definitions() {
+        let d = parse(
+            r#"{ "grid_width": 3, "grid_height": 1, "tile_width": 32, "tile_height": 32,
+                 "frames": { "run 2": [2, 0] } }"#,
+        )
+        .unwrap();
+
+        assert_eq!(d.pixels_per_unit, 8.0);
+        assert_eq!(d.pivot, [0.0, 0.0]);
+
+        let sprite = d.sprite();
+        let run = sprite.frame("run 2").unwrap();
+        assert_eq!(run.uv_min, [2.0 / 3.0, 0.0]);
+        assert_eq!((run.width, run.height), (32, 32));
+
+        assert!(matches!(
+            parse(
+                r#"{ "grid_width": 3, "grid_height": 1, "tile_width": 32, "tile_height": 32,
+                       "frames": { "run 3": [3, 0] } }"#
+            ),
+            Err(SpriteError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse(r#"{ "grid_width": 3, "grid_height": 1, "tile_width": 32 }"#),
+            Err(SpriteError::Definition(_))
+        ));
+    }
+
+    #[test]
+    fn moleman() {
+        let d = parse(include_str!("../../assets/moleman.png.sprite.json")).unwrap();
+
+        assert_eq!((d.grid_width, d.grid_height), (3, 1));
+    }
+}
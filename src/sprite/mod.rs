@@ -1,11 +1,22 @@
+pub mod animation;
+pub mod aseprite;
+pub mod atlas;
+pub mod batch;
+pub mod definition;
 pub mod sprite;
 pub mod sprite_data;
 pub use bevy_ecs::prelude as ecs;
-pub use sprite::Sprite;
+use bytemuck::{Pod, Zeroable};
+pub use sprite::{Sprite, SpriteFrame};
 use vulkano::{descriptor_set::WriteDescriptorSet, image::StorageImage};
 
 use crate::{engine, texture::Texture, uniform::Transformations};
 
+pub use self::animation::{animation_on_update, Animation, AnimationFinished, Animator};
+pub use self::aseprite::{import_aseprite, parse_aseprite, AsepriteError, AsepriteSheet};
+pub use self::atlas::{build_atlas, build_atlas_files, AtlasError, AtlasFrame, AtlasManifest};
+pub use self::batch::SpriteBatcher;
+pub use self::definition::{load_sprite, SpriteDefinition, SpriteError};
 pub use self::sprite_data::SpriteData;
 
 pub fn create_sprite_material(
@@ -16,25 +27,45 @@ pub fn create_sprite_material(
     let vs = sprite_vs::load(engine.device()).unwrap();
     let fs = sprite_fs::load(engine.device()).unwrap();
 
-    engine.create_material(
-        vs,
-        fs,
-        [
-            WriteDescriptorSet::buffer(0, globals.get_buffer()),
-            tex.describe(3),
-        ],
-    )
+    engine.create_material(vs, fs, Default::default(), sprite_descriptors(tex, globals))
 }
 
-pub struct SpritePushConstants {
-    world_x: f32,
-    world_y: f32,
-    tile_x: u32,
-    tile_y: u32,
-    tile_uv_x: f32,
-    tile_uv_y: f32,
-    tile_scale_x: f32,
-    tile_scale_y: f32,
+///Swap the texture a sprite material draws from, such as after it changed on disk
+pub fn update_sprite_material(
+    engine: &mut engine::Engine,
+    material: &engine::MatID,
+    tex: &Texture<StorageImage>,
+    globals: &Transformations,
+) {
+    engine.update_material(material, sprite_descriptors(tex, globals));
+}
+
+fn sprite_descriptors(
+    tex: &Texture<StorageImage>,
+    globals: &Transformations,
+) -> [WriteDescriptorSet; 2] {
+    [
+        WriteDescriptorSet::buffer(0, globals.get_buffer()),
+        tex.describe(3),
+    ]
+}
+
+///Everything needed to draw one sprite, laid out to match `Sprite` in the vertex shader
+#[derive(Copy, Clone, Debug, Zeroable, Pod)]
+#[repr(C)]
+pub struct SpriteInstance {
+    ///World position the pivot is placed at
+    world_pos: [f32; 2],
+    uv_min: [f32; 2],
+    uv_size: [f32; 2],
+    ///World size of the quad, negative to flip
+    scale: [f32; 2],
+    ///Point the quad is scaled, flipped and rotated around, from 0,0 at the bottom left to 1,1
+    pivot: [f32; 2],
+    rotation: f32,
+    _padding: f32,
+    ///Multiplied with the texture's colour
+    tint: [f32; 4],
 }
 
 mod sprite_vs {
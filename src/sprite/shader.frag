@@ -3,6 +3,7 @@
 
 layout(location = 0) in vec3 color;
 layout(location = 1) in vec2 uv;
+layout(location = 2) in vec4 tint;
 
 layout(location = 0) out vec4 f_color;
 
@@ -11,5 +12,5 @@ layout(binding = 3) uniform sampler2D texSampler;
 
 
 void main() {
-    f_color =   texture(texSampler, uv);
-}
\ No newline at end of file
+    f_color =   texture(texSampler, uv) * tint;
+}
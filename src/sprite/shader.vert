@@ -6,30 +6,45 @@ layout(location = 1) in vec3 color;
 
 layout(location = 0) out vec3 fragColor;
 layout(location = 1) out vec2 uv;
+layout(location = 2) out vec4 tint;
 
 
 layout(binding = 0) uniform Transforms{
 	mat4 world_to_screen;
 };
 
- 
-
-layout(push_constant) uniform constants {
+// Must match SpriteInstance in sprite/mod.rs
+struct Sprite {
     vec2 world_pos;
 
-	uint tile_x;
-	uint tile_y;
+	vec2 uv_min;
+	vec2 uv_size;
+	// Negative to flip
+	vec2 scale;
+	vec2 pivot;
+	float rotation;
+	vec4 tint;
+};
 
-	vec2 tile_uv_size; 
-	vec2 tile_scale;
+// One per instance, filled each frame by the SpriteBatcher
+layout(set = 1, binding = 0) readonly buffer Sprites {
+	Sprite sprites[];
 };
 
 
 void main() {
+	Sprite sprite = sprites[gl_InstanceIndex];
+
 	fragColor = color;
-  
+	tint = sprite.tint;
+
+	uv = sprite.uv_min + vec2(position.x, 1 - position.y) * sprite.uv_size;
 
-	uv = tile_uv_size * vec2(tile_x, tile_y) + vec2(position.x, 1 - position.y) * tile_uv_size;
+	// Scale, flip and rotate around the pivot, which sits on world_pos
+	vec2 local = (position - sprite.pivot) * sprite.scale;
+	float c = cos(sprite.rotation);
+	float s = sin(sprite.rotation);
+	local = mat2(c, s, -s, c) * local;
 
-    gl_Position = vec4( world_pos + position * tile_scale + vec2(tile_x, tile_y) , 0.0, 1.0) * world_to_screen;
-}
\ No newline at end of file
+    gl_Position = vec4( sprite.world_pos + local, 0.0, 1.0) * world_to_screen;
+}
@@ -1,7 +1,33 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
 use vulkano::image::StorageImage;
 
 use crate::texture::Texture;
 
+///A region of a sprite's texture to draw
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpriteFrame {
+    ///Top left corner in uv space
+    pub uv_min: [f32; 2],
+    ///Bottom right corner in uv space
+    pub uv_max: [f32; 2],
+    ///Size in pixels, which decides how big it is in the world
+    pub width: u32,
+    pub height: u32,
+    ///Overrides [`Sprite::pivot`] for just this frame
+    pub pivot: Option<[f32; 2]>,
+}
+
+impl SpriteFrame {
+    pub fn uv_size(&self) -> [f32; 2] {
+        [
+            self.uv_max[0] - self.uv_min[0],
+            self.uv_max[1] - self.uv_min[1],
+        ]
+    }
+}
+
 ///Stores texture related information
 pub struct Sprite {
     /// Amount of tiles horizontally
@@ -11,9 +37,36 @@ pub struct Sprite {
     ///Width of a tile (sub-sprite) inside the grid
     pub tile_width: usize,
     pub tile_height: usize,
+
+    ///How many pixels of the texture make up one unit in the world
+    pub pixels_per_unit: f32,
+    ///The point of a frame that sits on the entity's position, from `[0, 0]` at the bottom left
+    ///to `[1, 1]` at the top right
+    pub pivot: [f32; 2],
+
+    ///Frames that can be picked by name instead of by tile, such as ones packed into an atlas
+    pub frames: HashMap<String, SpriteFrame>,
 }
 
 impl Sprite {
+    ///A sheet split into a grid of `tile_width` by `tile_height` tiles, at 8 pixels per unit
+    pub fn new(
+        grid_width: usize,
+        grid_height: usize,
+        tile_width: usize,
+        tile_height: usize,
+    ) -> Self {
+        Self {
+            grid_width,
+            grid_height,
+            tile_width,
+            tile_height,
+            pixels_per_unit: 8.0,
+            pivot: [0.0, 0.0],
+            frames: HashMap::new(),
+        }
+    }
+
     /// Get the width and height of a tile in uv space
     pub fn tile_size_uv(&self) -> [f32; 2] {
         [1.0 / self.grid_width as f32, 1.0 / self.grid_height as f32]
@@ -27,4 +80,21 @@ impl Sprite {
             [tile_width * (x + 1) as f32, tile_height * (y + 1) as f32],
         )
     }
+
+    ///The frame for a tile of the grid
+    pub fn tile_frame(&self, x: usize, y: usize) -> SpriteFrame {
+        let (uv_min, uv_max) = self.position_uv(x, y);
+
+        SpriteFrame {
+            uv_min,
+            uv_max,
+            width: self.tile_width as u32,
+            height: self.tile_height as u32,
+            pivot: None,
+        }
+    }
+
+    pub fn frame(&self, name: &str) -> Option<&SpriteFrame> {
+        self.frames.get(name)
+    }
 }
@@ -1,34 +1,110 @@
-pub use crate::sprite::Sprite;
-use crate::transform::position::Position;
+pub use crate::sprite::{Sprite, SpriteFrame};
+use crate::transform::{Position, Rotation, Scale};
 pub use bevy_ecs::prelude as ecs;
 use std::sync::Arc;
 
-use super::SpritePushConstants;
+use super::SpriteInstance;
 
 ///The component to draw a sprite in the world
 ///
-///
+///Scale and rotation come from the entity's [`Scale`] and [`Rotation`], if it has them
 #[derive(ecs::Component)]
 pub struct SpriteData {
     pub sprite: Arc<Sprite>,
     /// Coordinate of tile to render
     pub tile_x: usize,
     pub tile_y: usize,
+    /// Name of a frame in [`Sprite::frames`] to render instead of the tile
+    pub frame: Option<String>,
+    /// Mirror the sprite around its pivot
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// RGBA colour multiplied with the texture, white leaves it unchanged
+    pub tint: [f32; 4],
+    /// Overrides the pivot of the frame and sprite, see [`Sprite::pivot`]
+    pub pivot: Option<[f32; 2]>,
 }
 
 impl SpriteData {
-    /// Generate push constants from context
-    pub fn get_push_constants(&self, pos: &Position) -> SpritePushConstants {
-        let [tile_uv_x, tile_uv_y] = self.sprite.tile_size_uv();
-        SpritePushConstants {
-            world_x: pos.0,
-            world_y: pos.1,
-            tile_x: self.tile_x as u32,
-            tile_y: self.tile_y as u32,
-            tile_uv_x,
-            tile_uv_y,
-            tile_scale_x: self.sprite.tile_width as f32 / 8.0,
-            tile_scale_y: self.sprite.tile_height as f32 / 8.0,
+    /// Draw the first tile of `sprite`, without any flipping or tint
+    pub fn new(sprite: Arc<Sprite>) -> Self {
+        Self {
+            sprite,
+            tile_x: 0,
+            tile_y: 0,
+            frame: None,
+            flip_x: false,
+            flip_y: false,
+            tint: [1.0; 4],
+            pivot: None,
         }
     }
+
+    /// The region of the sheet to draw, falling back to the tile if the named frame is missing
+    pub fn current_frame(&self) -> SpriteFrame {
+        self.frame
+            .as_deref()
+            .and_then(|name| self.sprite.frame(name))
+            .copied()
+            .unwrap_or_else(|| self.sprite.tile_frame(self.tile_x, self.tile_y))
+    }
+
+    /// The pivot in use, from most to least specific
+    pub fn current_pivot(&self, frame: &SpriteFrame) -> [f32; 2] {
+        self.pivot.or(frame.pivot).unwrap_or(self.sprite.pivot)
+    }
+
+    /// Generate the instance data to draw with from context
+    pub fn get_instance(
+        &self,
+        pos: &Position,
+        scale: Option<&Scale>,
+        rotation: Option<&Rotation>,
+    ) -> SpriteInstance {
+        let frame = self.current_frame();
+        let Scale(scale_x, scale_y) = scale.copied().unwrap_or_default();
+        let flip = |flipped: bool| if flipped { -1.0 } else { 1.0 };
+
+        SpriteInstance {
+            world_pos: [pos.0, pos.1],
+            uv_min: frame.uv_min,
+            uv_size: frame.uv_size(),
+            scale: [
+                frame.width as f32 / self.sprite.pixels_per_unit * scale_x * flip(self.flip_x),
+                frame.height as f32 / self.sprite.pixels_per_unit * scale_y * flip(self.flip_y),
+            ],
+            pivot: self.current_pivot(&frame),
+            rotation: rotation.map_or(0.0, |r| r.0),
+            _padding: 0.0,
+            tint: self.tint,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instances() {
+        let mut sprite = Sprite::new(3, 1, 32, 32);
+        sprite.pivot = [0.5, 0.0];
+        let mut data = SpriteData::new(Arc::new(sprite));
+
+        let p = data.get_instance(&Position(1.0, 2.0), None, None);
+        assert_eq!(p.world_pos, [1.0, 2.0]);
+        assert_eq!(p.scale, [4.0, 4.0]);
+        assert_eq!(p.pivot, [0.5, 0.0]);
+
+        data.flip_x = true;
+        data.pivot = Some([0.0, 1.0]);
+        let p = data.get_instance(
+            &Position(0.0, 0.0),
+            Some(&Scale(2.0, 0.5)),
+            Some(&Rotation(1.0)),
+        );
+        assert_eq!(p.scale, [-8.0, 2.0]);
+        assert_eq!(p.pivot, [0.0, 1.0]);
+        assert_eq!(p.rotation, 1.0);
+    }
 }
@@ -1,4 +1,9 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
+
+use image::{
+    error::{DecodingError, ImageFormatHint},
+    RgbaImage,
+};
 
 use vulkano::{
     buffer::{BufferUsage, CpuAccessibleBuffer},
@@ -15,7 +20,10 @@ use vulkano::{
     sync::{self, GpuFuture},
 };
 
-use crate::engine::Engine;
+use crate::{
+    engine::Engine,
+    pdn::{self, PdnError},
+};
 
 pub trait AnyTexture: ImageAccess {}
 
@@ -84,20 +92,51 @@ impl<I: AnyTexture + 'static> Texture<I> {
 
 impl Texture<StorageImage> {
     pub(crate) fn load(path: &str, engine: &Engine) -> Self {
-        let image = upload_image(path, engine.device(), engine.queue());
+        Self::try_load(path, engine).unwrap()
+    }
+
+    ///Load a texture, giving back an error instead of panicking if the image can't be read
+    pub(crate) fn try_load(path: &str, engine: &Engine) -> Result<Self, image::ImageError> {
+        let image = upload_image(path, engine.device(), engine.queue())?;
 
-        Texture::new(image, engine.device())
+        Ok(Texture::new(image, engine.device()))
     }
 }
 
-fn upload_image(filename: &str, device: Arc<Device>, queue: Arc<Queue>) -> Arc<StorageImage> {
-    let img = image::io::Reader::open(filename).unwrap().decode().unwrap();
+///Read an image file as RGBA, flattening Paint.NET files so the source art can be used directly
+pub fn open_image(path: impl AsRef<Path>) -> Result<RgbaImage, image::ImageError> {
+    let path = path.as_ref();
+
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("pdn"))
+    {
+        return match pdn::load_pdn(path) {
+            Ok(document) => Ok(document.flatten()),
+            Err(PdnError::Io(e)) => Err(e.into()),
+            Err(e) => Err(image::ImageError::Decoding(DecodingError::new(
+                ImageFormatHint::Name(String::from("pdn")),
+                e,
+            ))),
+        };
+    }
+
+    Ok(image::io::Reader::open(path)?.decode()?.to_rgba8())
+}
+
+fn upload_image(
+    filename: &str,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+) -> Result<Arc<StorageImage>, image::ImageError> {
+    //Always RGBA, as that's the format of the image we upload to
+    let img = open_image(filename)?;
 
     let buf = CpuAccessibleBuffer::from_iter(
         device.clone(),
         BufferUsage::transfer_source(),
         false,
-        img.as_bytes().iter().map(|x| *x),
+        img.as_raw().iter().copied(),
     )
     .expect("failed to create buffer");
 
@@ -134,5 +173,5 @@ fn upload_image(filename: &str, device: Arc<Device>, queue: Arc<Queue>) -> Arc<S
         .flush()
         .unwrap();
 
-    image
+    Ok(image)
 }
@@ -0,0 +1,496 @@
+use std::fmt;
+
+use super::{Terrain, TileStamp};
+
+#[cfg(test)]
+use super::{MergeMode, Orientation, Tile, TileGrid};
+
+/// Which character stands for each [`Terrain`] in a text encoded map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Legend {
+    entries: Vec<(char, Terrain)>,
+}
+
+impl Default for Legend {
+    /// `#` for solid ground and `.` for tunnels
+    fn default() -> Self {
+        Self {
+            entries: vec![('#', Terrain::Filled), ('.', Terrain::Empty)],
+        }
+    }
+}
+
+impl Legend {
+    #[cfg(test)]
+    pub fn new(entries: Vec<(char, Terrain)>) -> Self {
+        Self { entries }
+    }
+
+    pub fn terrain(&self, c: char) -> Option<Terrain> {
+        self.entries.iter().find(|(e, _)| *e == c).map(|(_, t)| *t)
+    }
+
+    /// The character for `terrain`, or `?` if the legend doesn't have one
+    pub fn char(&self, terrain: Terrain) -> char {
+        self.entries
+            .iter()
+            .find(|(_, t)| *t == terrain)
+            .map_or('?', |(c, _)| *c)
+    }
+
+    /// Parse the `#=Filled .=Empty` part of a legend line
+    fn parse(s: &str) -> Result<Self, AsciiError> {
+        let entries = s
+            .split_whitespace()
+            .map(|entry| {
+                let mut chars = entry.chars();
+
+                match (chars.next(), chars.next(), chars.as_str()) {
+                    (Some(c), Some('='), name) => Terrain::ALL
+                        .into_iter()
+                        .find(|t| format!("{:?}", t) == name)
+                        .map(|t| (c, t))
+                        .ok_or_else(|| AsciiError::BadLegend(entry.to_owned())),
+                    _ => Err(AsciiError::BadLegend(entry.to_owned())),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { entries })
+    }
+}
+
+impl fmt::Display for Legend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (c, t)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}={:?}", c, t)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsciiError {
+    MissingSize,
+    BadSize(String),
+    BadLegend(String),
+    UnknownChar {
+        c: char,
+        row: usize,
+        column: usize,
+    },
+    WrongRowLength {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    WrongRowCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for AsciiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsciiError::MissingSize => write!(f, "missing a `size WxH` line"),
+            AsciiError::BadSize(s) => write!(f, "can't read size `{}`", s),
+            AsciiError::BadLegend(s) => write!(f, "can't read legend entry `{}`", s),
+            AsciiError::UnknownChar { c, row, column } => write!(
+                f,
+                "`{}` at row {} column {} isn't in the legend",
+                c, row, column
+            ),
+            AsciiError::WrongRowLength {
+                row,
+                expected,
+                found,
+            } => write!(f, "row {} is {} wide, expected {}", row, found, expected),
+            AsciiError::WrongRowCount { expected, found } => {
+                write!(f, "found {} rows, expected {}", found, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsciiError {}
+
+impl TileStamp {
+    /// Encode as a `size` line, a `legend` line and then one line per row, top row first
+    ///
+    /// ```text
+    /// size 3x2
+    /// legend #=Filled .=Empty
+    /// #..
+    /// ###
+    /// ```
+    pub fn to_text(&self, legend: &Legend) -> String {
+        let mut text = format!(
+            "size {}x{}\nlegend {}\n",
+            self.width(),
+            self.height(),
+            legend
+        );
+
+        text.push_str(&self.to_rows(legend));
+        text
+    }
+
+    /// Just the rows of [`TileStamp::to_text`], one line each, top row first
+    pub fn to_rows(&self, legend: &Legend) -> String {
+        let mut text = String::new();
+
+        for y in (0..self.height()).rev() {
+            text.extend((0..self.width()).map(|x| legend.char(self.terrain(x, y))));
+            text.push('\n');
+        }
+
+        text
+    }
+
+    /// Decode text written by [`TileStamp::to_text`]
+    ///
+    /// Blank lines and code fences are skipped, so snippets can be pasted straight out of chat.
+    /// Without a `legend` line, the default [`Legend`] is used
+    pub fn from_text(text: &str) -> Result<Self, AsciiError> {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with("```"));
+
+        let size = lines
+            .next()
+            .and_then(|l| l.strip_prefix("size "))
+            .ok_or(AsciiError::MissingSize)?;
+
+        let (width, height) = size
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+            .filter(|&(w, h): &(usize, usize)| w.checked_mul(h).is_some())
+            .ok_or_else(|| AsciiError::BadSize(size.to_owned()))?;
+
+        let mut lines = lines.peekable();
+
+        let legend = match lines.peek().and_then(|l| l.strip_prefix("legend ")) {
+            Some(l) => {
+                lines.next();
+                Legend::parse(l)?
+            }
+            None => Legend::default(),
+        };
+
+        let rows: Vec<&str> = lines.collect();
+
+        if rows.len() != height {
+            return Err(AsciiError::WrongRowCount {
+                expected: height,
+                found: rows.len(),
+            });
+        }
+
+        Self::parse_rows(&rows, width, &legend)
+    }
+
+    /// Decode just rows of text, top row first, taking the size from the rows themselves
+    ///
+    /// Blank lines and leading or trailing whitespace are skipped
+    #[cfg(test)]
+    pub fn from_rows(text: &str, legend: &Legend) -> Result<Self, AsciiError> {
+        let rows: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+
+        let width = rows.first().map_or(0, |r| r.chars().count());
+
+        Self::parse_rows(&rows, width, legend)
+    }
+
+    fn parse_rows(rows: &[&str], width: usize, legend: &Legend) -> Result<Self, AsciiError> {
+        let height = rows.len();
+
+        //The width may come from a header, so check it against the rows before allocating for it
+        for (row, line) in rows.iter().enumerate() {
+            let found = line.chars().count();
+            if found != width {
+                return Err(AsciiError::WrongRowLength {
+                    row,
+                    expected: width,
+                    found,
+                });
+            }
+        }
+
+        let mut stamp = TileStamp::new(width, height, Terrain::Empty);
+
+        for (row, line) in rows.iter().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let terrain =
+                    legend
+                        .terrain(c)
+                        .ok_or(AsciiError::UnknownChar { c, row, column })?;
+
+                stamp.set_terrain(column, height - 1 - row, terrain);
+            }
+        }
+
+        Ok(stamp)
+    }
+}
+
+///Only needed by tests, to write grids out by hand and explain failures
+#[cfg(test)]
+impl TileGrid {
+    /// Build a grid from rows of text, top row first, with orientations worked out from the terrain
+    ///
+    /// ```text
+    /// ####
+    /// #..#
+    /// ####
+    /// ```
+    pub fn from_ascii(text: &str, legend: &Legend) -> Result<Self, AsciiError> {
+        let stamp = TileStamp::from_rows(text, legend)?;
+
+        let mut grid = TileGrid::new(stamp.width(), stamp.height());
+        stamp.paste(&mut grid, 0, 0, MergeMode::Overwrite);
+
+        Ok(grid)
+    }
+
+    /// The terrain of every cell as rows of text, top row first
+    pub fn to_ascii(&self, legend: &Legend) -> String {
+        TileStamp::capture(self, (0, 0), (self.width(), self.height())).to_rows(legend)
+    }
+
+    /// Draw every cell as a 3x3 block showing which of its neighbours it is oriented towards
+    ///
+    /// Empty cells are left blank, and the centre of a filled cell is `o`
+    pub fn orientation_map(&self) -> String {
+        let mut text = String::new();
+
+        for y in (0..self.height()).rev() {
+            for off_y in [1, 0, -1] {
+                for x in 0..self.width() {
+                    if x > 0 {
+                        text.push(' ');
+                    }
+
+                    for off_x in [-1, 0, 1] {
+                        text.push(match (self.tile(x, y), Orientation::orient(off_x, off_y)) {
+                            (Tile::None, _) => ' ',
+                            (Tile::Filled(_), None) => 'o',
+                            (Tile::Filled(o), Some(dir)) if o.contains(dir) => '#',
+                            (Tile::Filled(_), Some(_)) => '.',
+                        });
+                    }
+                }
+                text.push('\n');
+            }
+        }
+
+        text
+    }
+}
+
+/// Compare the orientations of two grids, describing every difference
+///
+/// ## Returns
+/// [`None`] if they match, otherwise both orientation maps side by side followed by a list of
+/// the cells that differ
+#[cfg(test)]
+pub(crate) fn orientation_diff(expected: &TileGrid, actual: &TileGrid) -> Option<String> {
+    if (expected.width(), expected.height()) != (actual.width(), actual.height()) {
+        return Some(format!(
+            "expected a {}x{} grid, found {}x{}",
+            expected.width(),
+            expected.height(),
+            actual.width(),
+            actual.height()
+        ));
+    }
+
+    let mut cells = String::new();
+
+    for y in (0..expected.height()).rev() {
+        for x in 0..expected.width() {
+            let (e, a) = (expected.tile(x, y), actual.tile(x, y));
+
+            if e != a {
+                cells.push_str(&format!(
+                    "  ({}, {}): expected {:?}, found {:?}\n",
+                    x, y, e, a
+                ));
+            }
+        }
+    }
+
+    if cells.is_empty() {
+        return None;
+    }
+
+    let expected_map = expected.orientation_map();
+    let column = expected.width() * 4;
+
+    let mut text = format!("{:<column$} | actual\n", "expected", column = column);
+    for (e, a) in expected_map.lines().zip(actual.orientation_map().lines()) {
+        text.push_str(&format!("{:<column$} | {}\n", e, a, column = column));
+    }
+
+    text.push_str("differences:\n");
+    text.push_str(&cells);
+
+    Some(text)
+}
+
+/// Panic with an [`orientation_diff`] if the grids don't match
+#[cfg(test)]
+#[track_caller]
+pub(crate) fn assert_orientations_eq(expected: &TileGrid, actual: &TileGrid) {
+    if let Some(diff) = orientation_diff(expected, actual) {
+        panic!("orientations differ\n{}", diff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut s = TileStamp::new(3, 2, Terrain::Empty);
+        s.set_terrain(0, 0, Terrain::Filled);
+        s.set_terrain(1, 0, Terrain::Filled);
+        s.set_terrain(2, 0, Terrain::Filled);
+        s.set_terrain(0, 1, Terrain::Filled);
+
+        let text = s.to_text(&Legend::default());
+        assert_eq!(text, "size 3x2\nlegend #=Filled .=Empty\n#..\n###\n");
+        assert_eq!(TileStamp::from_text(&text), Ok(s.clone()));
+
+        let custom = Legend::new(vec![('X', Terrain::Filled), ('-', Terrain::Empty)]);
+        let text = s.to_text(&custom);
+        assert_eq!(TileStamp::from_text(&text), Ok(s));
+    }
+
+    #[test]
+    fn pasted_from_chat() {
+        let text = "\n```\n  size 2x2\n  ##\n  .#\n```\n";
+        let s = TileStamp::from_text(text).unwrap();
+
+        assert_eq!(s.terrain(0, 0), Terrain::Empty);
+        assert_eq!(s.terrain(0, 1), Terrain::Filled);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(TileStamp::from_text("##\n##"), Err(AsciiError::MissingSize));
+        assert_eq!(
+            TileStamp::from_text("size 2by2\n##\n##"),
+            Err(AsciiError::BadSize("2by2".into()))
+        );
+        assert_eq!(
+            TileStamp::from_text("size 2x2\nlegend #=Lava\n##\n##"),
+            Err(AsciiError::BadLegend("#=Lava".into()))
+        );
+        assert_eq!(
+            TileStamp::from_text("size 2x2\n##\n#"),
+            Err(AsciiError::WrongRowLength {
+                row: 1,
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(
+            TileStamp::from_text("size 2x3\n##\n##"),
+            Err(AsciiError::WrongRowCount {
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            TileStamp::from_text("size 2x1\n#o"),
+            Err(AsciiError::UnknownChar {
+                c: 'o',
+                row: 0,
+                column: 1
+            })
+        );
+    }
+
+    #[test]
+    fn huge_sizes() {
+        // Rejected before anything is allocated for them
+        assert_eq!(
+            TileStamp::from_text("size 100000000000x1\n#"),
+            Err(AsciiError::WrongRowLength {
+                row: 0,
+                expected: 100000000000,
+                found: 1
+            })
+        );
+
+        let size = format!("{0}x{0}", usize::MAX / 2);
+        assert_eq!(
+            TileStamp::from_text(&format!("size {}\n#", size)),
+            Err(AsciiError::BadSize(size))
+        );
+    }
+
+    const ROOM: &str = "
+        #####
+        #...#
+        #..##
+        #####
+    ";
+
+    #[test]
+    fn grid_round_trip() {
+        let grid = TileGrid::from_ascii(ROOM, &Legend::default()).unwrap();
+
+        assert_eq!((grid.width(), grid.height()), (5, 4));
+        assert_eq!(grid.terrain(1, 2), Terrain::Empty);
+        assert_eq!(grid.terrain(3, 1), Terrain::Filled);
+
+        let text = grid.to_ascii(&Legend::default());
+        assert_eq!(text, "#####\n#...#\n#..##\n#####\n");
+    }
+
+    #[test]
+    fn orientations_match_toggling() {
+        let parsed = TileGrid::from_ascii(ROOM, &Legend::default()).unwrap();
+
+        let mut toggled = TileGrid::new(5, 4);
+        for (x, y) in [(1, 2), (2, 2), (3, 2), (1, 1), (2, 1)] {
+            toggled.toggle(x, y);
+        }
+
+        assert_orientations_eq(&parsed, &toggled);
+    }
+
+    #[test]
+    fn diff_lists_cells() {
+        let a = TileGrid::from_ascii("##\n##", &Legend::default()).unwrap();
+        let b = TileGrid::from_ascii("##\n#.", &Legend::default()).unwrap();
+
+        assert_eq!(orientation_diff(&a, &a), None);
+
+        let diff = orientation_diff(&a, &b).unwrap();
+        assert!(diff.contains("(1, 0): expected Filled"), "{}", diff);
+        assert!(diff.contains("(0, 1): expected"), "{}", diff);
+        // Both maps are shown, one line per row of blocks plus the titles
+        assert_eq!(diff.lines().take_while(|l| l.contains('|')).count(), 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "orientations differ")]
+    fn assert_panics() {
+        let a = TileGrid::from_ascii("..\n##", &Legend::default()).unwrap();
+        let b = TileGrid::from_ascii("##\n##", &Legend::default()).unwrap();
+
+        assert_orientations_eq(&a, &b);
+    }
+}
@@ -0,0 +1,332 @@
+use super::{GridCoordinate, Orientation, TileRequirements, TilemapSpriteConfig};
+
+/// A single undoable change to a [`TilemapSpriteConfig`]'s rules
+#[derive(Clone, Copy)]
+pub enum ConfigEdit {
+    AddEntry {
+        at: GridCoordinate,
+        reqs: TileRequirements,
+    },
+    DeleteEntry {
+        at: GridCoordinate,
+        reqs: TileRequirements,
+    },
+    ChangeRequirement {
+        at: GridCoordinate,
+        dir: Orientation,
+        old: Option<bool>,
+        new: Option<bool>,
+    },
+    ChangeWeight {
+        at: GridCoordinate,
+        old: u32,
+        new: u32,
+    },
+}
+
+impl ConfigEdit {
+    ///The edit that undoes this one
+    pub fn inverse(self) -> Self {
+        match self {
+            ConfigEdit::AddEntry { at, reqs } => ConfigEdit::DeleteEntry { at, reqs },
+            ConfigEdit::DeleteEntry { at, reqs } => ConfigEdit::AddEntry { at, reqs },
+            ConfigEdit::ChangeRequirement { at, dir, old, new } => ConfigEdit::ChangeRequirement {
+                at,
+                dir,
+                old: new,
+                new: old,
+            },
+            ConfigEdit::ChangeWeight { at, old, new } => ConfigEdit::ChangeWeight {
+                at,
+                old: new,
+                new: old,
+            },
+        }
+    }
+
+    ///Make the change, without syncing the config's coordinates
+    pub fn apply(&self, config: &mut TilemapSpriteConfig) {
+        match *self {
+            ConfigEdit::AddEntry { at, reqs } => {
+                config.orientations.insert(at, reqs);
+            }
+            ConfigEdit::DeleteEntry { at, .. } => {
+                config.orientations.remove(&at);
+            }
+            ConfigEdit::ChangeRequirement { at, dir, new, .. } => {
+                if let Some(Ok(r)) = config
+                    .orientations
+                    .get_mut(&at)
+                    .map(|reqs| reqs.get_requirement_mut(dir))
+                {
+                    *r = new;
+                }
+            }
+            ConfigEdit::ChangeWeight { at, new, .. } => {
+                if let Some(reqs) = config.orientations.get_mut(&at) {
+                    reqs.weight = new;
+                }
+            }
+        }
+    }
+}
+
+/// Undo and redo stacks for the config editor, which also track whether there are unsaved changes
+///
+/// Each entry is a group of edits made by one action, like deleting a box of cells
+#[derive(Default)]
+pub struct ConfigHistory {
+    undo: Vec<Vec<ConfigEdit>>,
+    redo: Vec<Vec<ConfigEdit>>,
+    ///How deep the undo stack was when the config was last saved, `None` if that state is gone
+    saved_depth: Option<usize>,
+    ///Something that can't be undone changed since the last save
+    untracked: bool,
+}
+
+impl ConfigHistory {
+    pub fn new() -> Self {
+        Self {
+            saved_depth: Some(0),
+            ..Default::default()
+        }
+    }
+
+    ///Apply `edits` to `config` and remember them so they can be undone
+    ///
+    ///Dragging a weight changes it every frame, so weight changes to the same cell are merged
+    pub fn push(&mut self, config: &mut TilemapSpriteConfig, edits: Vec<ConfigEdit>) {
+        if edits.is_empty() {
+            return;
+        }
+
+        for e in &edits {
+            e.apply(config);
+        }
+
+        if let Some(saved) = self.saved_depth {
+            if saved > self.undo.len() {
+                //The saved state was on the redo stack, which is about to be lost
+                self.saved_depth = None;
+            }
+        }
+        self.redo.clear();
+
+        //Merging into the saved edit would make the saved state unreachable
+        let can_merge = self.saved_depth != Some(self.undo.len());
+
+        let merged = match (self.undo.last_mut(), edits.as_slice()) {
+            (Some(last), [ConfigEdit::ChangeWeight { at, new, .. }]) if can_merge => {
+                match last.as_mut_slice() {
+                    [ConfigEdit::ChangeWeight {
+                        at: last_at,
+                        new: last_new,
+                        ..
+                    }] if last_at == at => {
+                        *last_new = *new;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        };
+
+        if !merged {
+            self.undo.push(edits);
+        }
+    }
+
+    ///Undo the last group of edits, returning false if there was nothing to undo
+    pub fn undo(&mut self, config: &mut TilemapSpriteConfig) -> bool {
+        match self.undo.pop() {
+            Some(edits) => {
+                for e in edits.iter().rev() {
+                    e.inverse().apply(config);
+                }
+                self.redo.push(edits);
+                true
+            }
+            None => false,
+        }
+    }
+
+    ///Redo the last undone group of edits, returning false if there was nothing to redo
+    pub fn redo(&mut self, config: &mut TilemapSpriteConfig) -> bool {
+        match self.redo.pop() {
+            Some(edits) => {
+                for e in &edits {
+                    e.apply(config);
+                }
+                self.undo.push(edits);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    ///If the config has changed since it was last saved or loaded
+    pub fn is_dirty(&self) -> bool {
+        self.untracked || self.saved_depth != Some(self.undo.len())
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_depth = Some(self.undo.len());
+        self.untracked = false;
+    }
+
+    ///Record a change that can't be undone, like a new tile size
+    ///
+    ///Edits from before it might not make sense anymore, so they are forgotten
+    pub fn mark_changed(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.saved_depth = None;
+        self.untracked = true;
+    }
+
+    ///Forget everything, for a config that was just loaded
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weight(c: &TilemapSpriteConfig, at: (usize, usize)) -> Option<u32> {
+        c.orientations.get(&at.into()).map(|r| r.weight)
+    }
+
+    fn add(at: (usize, usize)) -> Vec<ConfigEdit> {
+        vec![ConfigEdit::AddEntry {
+            at: at.into(),
+            reqs: TileRequirements::default(),
+        }]
+    }
+
+    fn set_weight(c: &TilemapSpriteConfig, at: (usize, usize), new: u32) -> Vec<ConfigEdit> {
+        vec![ConfigEdit::ChangeWeight {
+            at: at.into(),
+            old: weight(c, at).unwrap(),
+            new,
+        }]
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut c = TilemapSpriteConfig::new(4, 4);
+        let mut h = ConfigHistory::new();
+
+        h.push(&mut c, add((1, 1)));
+        h.push(
+            &mut c,
+            vec![ConfigEdit::ChangeRequirement {
+                at: (1, 1).into(),
+                dir: Orientation::N,
+                old: None,
+                new: Some(true),
+            }],
+        );
+        assert_eq!(c.orientations[&(1, 1).into()].dirs[0], Some(true));
+
+        assert!(h.undo(&mut c));
+        assert_eq!(c.orientations[&(1, 1).into()].dirs[0], None);
+        assert!(h.undo(&mut c));
+        assert!(c.orientations.is_empty());
+        assert!(!h.undo(&mut c));
+
+        assert!(h.redo(&mut c));
+        assert!(h.redo(&mut c));
+        assert_eq!(c.orientations[&(1, 1).into()].dirs[0], Some(true));
+        assert!(!h.can_redo());
+
+        //A new edit throws away anything that could be redone
+        h.undo(&mut c);
+        h.push(&mut c, add((2, 2)));
+        assert!(!h.can_redo());
+    }
+
+    #[test]
+    fn delete_restores_requirements() {
+        let mut c = TilemapSpriteConfig::new(4, 4);
+        let mut h = ConfigHistory::new();
+
+        h.push(&mut c, add((0, 0)));
+        let e = set_weight(&c, (0, 0), 5);
+        h.push(&mut c, e);
+
+        let reqs = c.orientations[&(0, 0).into()];
+        h.push(
+            &mut c,
+            vec![ConfigEdit::DeleteEntry {
+                at: (0, 0).into(),
+                reqs,
+            }],
+        );
+        assert_eq!(weight(&c, (0, 0)), None);
+
+        h.undo(&mut c);
+        assert_eq!(weight(&c, (0, 0)), Some(5));
+    }
+
+    #[test]
+    fn weight_drags_merge() {
+        let mut c = TilemapSpriteConfig::new(4, 4);
+        let mut h = ConfigHistory::new();
+
+        h.push(&mut c, add((0, 0)));
+        h.push(&mut c, add((1, 0)));
+        for w in 2..6 {
+            let e = set_weight(&c, (0, 0), w);
+            h.push(&mut c, e);
+        }
+        let e = set_weight(&c, (1, 0), 3);
+        h.push(&mut c, e);
+
+        h.undo(&mut c);
+        assert_eq!(weight(&c, (1, 0)), Some(1));
+        assert_eq!(weight(&c, (0, 0)), Some(5));
+        h.undo(&mut c);
+        assert_eq!(weight(&c, (0, 0)), Some(1));
+    }
+
+    #[test]
+    fn dirty_tracking() {
+        let mut c = TilemapSpriteConfig::new(4, 4);
+        let mut h = ConfigHistory::new();
+        assert!(!h.is_dirty());
+
+        h.push(&mut c, add((0, 0)));
+        assert!(h.is_dirty());
+        h.mark_saved();
+        assert!(!h.is_dirty());
+
+        //Undoing back to the saved state is clean again
+        let e = set_weight(&c, (0, 0), 2);
+        h.push(&mut c, e);
+        assert!(h.is_dirty());
+        h.undo(&mut c);
+        assert!(!h.is_dirty());
+
+        //The saved state can't come back once the edit leading to it is replaced
+        h.undo(&mut c);
+        h.push(&mut c, add((1, 1)));
+        h.undo(&mut c);
+        assert!(h.is_dirty());
+
+        h.mark_changed();
+        assert!(h.is_dirty() && !h.can_undo());
+        h.clear();
+        assert!(!h.is_dirty());
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use super::{GridCoordinate, TileRequirements, TilemapSpriteConfig};
+
+/// The layout written by [`TilemapSpriteConfig::save`]
+///
+/// Bump this and add to [`MIGRATIONS`] whenever the layout changes
+pub const CONFIG_VERSION: u64 = 3;
+
+/// `MIGRATIONS[n]` upgrades a version `n` config to version `n + 1`
+const MIGRATIONS: [fn(&mut Map<String, Value>); CONFIG_VERSION as usize] =
+    [v0_to_v1, v1_to_v2, v2_to_v3];
+
+/// Configs from before versioning had no `version` field, but are otherwise the same
+fn v0_to_v1(config: &mut Map<String, Value>) {
+    config.insert("version".into(), 1.into());
+}
+
+/// Version 2 added padding and margin, and stores the sheet size instead of assuming the tiles
+/// fill it exactly
+fn v1_to_v2(config: &mut Map<String, Value>) {
+    let size =
+        |count: &str, tile: &str| Some(config.get(count)?.as_u64()? * config.get(tile)?.as_u64()?);
+
+    //Leave anything missing for the reader to complain about
+    if let (Some(width), Some(height)) = (
+        size("grid_width", "tile_width"),
+        size("grid_height", "tile_height"),
+    ) {
+        config.insert("image_width".into(), width.into());
+        config.insert("image_height".into(), height.into());
+    }
+
+    config.insert("padding".into(), 0.into());
+    config.insert("margin".into(), 0.into());
+    config.insert("version".into(), 2.into());
+}
+
+/// Version 3 added weights to rules, which used to all be equally likely
+fn v2_to_v3(config: &mut Map<String, Value>) {
+    if let Some(Value::Object(orientations)) = config.get_mut("orientations") {
+        for rule in orientations.values_mut() {
+            if let Value::Object(rule) = rule {
+                rule.insert("weight".into(), 1.into());
+            }
+        }
+    }
+
+    config.insert("version".into(), 3.into());
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        file: PathBuf,
+        source: io::Error,
+    },
+    /// Not valid JSON at all, or couldn't be written as JSON
+    Syntax {
+        file: PathBuf,
+        source: serde_json::Error,
+    },
+    /// Valid JSON, but the value at `path` isn't what we expected
+    Invalid {
+        file: PathBuf,
+        path: String,
+        message: String,
+    },
+    /// Written by a newer version of the game
+    UnsupportedVersion {
+        file: PathBuf,
+        version: u64,
+    },
+    /// Saving or reloading a config that was never loaded from or saved to a file
+    NoSource,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { file, source } => write!(f, "{}: {}", file.display(), source),
+            ConfigError::Syntax { file, source } => write!(f, "{}: {}", file.display(), source),
+            ConfigError::Invalid {
+                file,
+                path,
+                message,
+            } => write!(f, "{}: at {}: {}", file.display(), path, message),
+            ConfigError::UnsupportedVersion { file, version } => write!(
+                f,
+                "{}: version {} is newer than the supported version {}",
+                file.display(),
+                version,
+                CONFIG_VERSION
+            ),
+            ConfigError::NoSource => write!(f, "config has no file to save to or reload from"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Syntax { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a config piece by piece, so errors can say exactly where they happened
+struct Reader<'a> {
+    file: &'a Path,
+}
+
+impl Reader<'_> {
+    fn invalid(&self, path: &str, message: impl fmt::Display) -> ConfigError {
+        ConfigError::Invalid {
+            file: self.file.to_owned(),
+            path: path.to_owned(),
+            message: message.to_string(),
+        }
+    }
+
+    fn object<'v>(
+        &self,
+        value: &'v Value,
+        path: &str,
+    ) -> Result<&'v Map<String, Value>, ConfigError> {
+        value
+            .as_object()
+            .ok_or_else(|| self.invalid(path, format!("expected an object, found {}", value)))
+    }
+
+    fn field<'v>(
+        &self,
+        object: &'v Map<String, Value>,
+        path: &str,
+        name: &str,
+    ) -> Result<(&'v Value, String), ConfigError> {
+        let path = format!("{}.{}", path, name);
+
+        match object.get(name) {
+            Some(v) => Ok((v, path)),
+            None => Err(self.invalid(&path, "missing field")),
+        }
+    }
+
+    fn typed<T: DeserializeOwned>(&self, value: &Value, path: &str) -> Result<T, ConfigError> {
+        T::deserialize(value).map_err(|e| self.invalid(path, e))
+    }
+
+    ///Read the rule at `path`
+    fn requirements(&self, value: &Value, path: &str) -> Result<TileRequirements, ConfigError> {
+        let object = self.object(value, path)?;
+        let (dirs, dirs_path) = self.field(object, path, "dirs")?;
+
+        let dirs = match dirs.as_array() {
+            Some(d) if d.len() == 8 => d,
+            _ => {
+                return Err(
+                    self.invalid(&dirs_path, format!("expected 8 directions, found {}", dirs))
+                )
+            }
+        };
+
+        let mut requirements = TileRequirements::default();
+        for (i, (d, r)) in dirs.iter().zip(&mut requirements.dirs).enumerate() {
+            *r = self.typed(d, &format!("{}[{}]", dirs_path, i))?;
+        }
+
+        let (weight, weight_path) = self.field(object, path, "weight")?;
+        requirements.weight = self.typed(weight, &weight_path)?;
+
+        Ok(requirements)
+    }
+
+    fn config(&self, value: &Value) -> Result<TilemapSpriteConfig, ConfigError> {
+        let root = self.object(value, "$")?;
+
+        let usize_field = |name| {
+            let (v, path) = self.field(root, "$", name)?;
+            self.typed::<usize>(v, &path)
+        };
+
+        let mut config =
+            TilemapSpriteConfig::new(usize_field("grid_width")?, usize_field("grid_height")?);
+        config.tile_width = usize_field("tile_width")?;
+        config.tile_height = usize_field("tile_height")?;
+        config.padding = usize_field("padding")?;
+        config.margin = usize_field("margin")?;
+        config.image_width = usize_field("image_width")?;
+        config.image_height = usize_field("image_height")?;
+
+        let (orientations, path) = self.field(root, "$", "orientations")?;
+        for (k, v) in self.object(orientations, &path)? {
+            let path = format!("{}[{:?}]", path, k);
+
+            let coordinate: GridCoordinate = self.typed(&Value::String(k.clone()), &path)?;
+            config
+                .orientations
+                .insert(coordinate, self.requirements(v, &path)?);
+        }
+
+        Ok(config)
+    }
+}
+
+/// Parse the contents of a config file, upgrading it from any older version
+///
+/// `file` is only used to name the file in errors
+pub fn parse_config(json: &str, file: &Path) -> Result<TilemapSpriteConfig, ConfigError> {
+    let reader = Reader { file };
+
+    let mut value: Value = serde_json::from_str(json).map_err(|source| ConfigError::Syntax {
+        file: file.to_owned(),
+        source,
+    })?;
+
+    let root = match value.as_object_mut() {
+        Some(r) => r,
+        None => return Err(reader.invalid("$", "expected an object")),
+    };
+
+    let version = match root.get("version") {
+        None => 0,
+        Some(v) => v.as_u64().ok_or_else(|| {
+            reader.invalid(
+                "$.version",
+                format!("expected a version number, found {}", v),
+            )
+        })?,
+    };
+
+    if version > CONFIG_VERSION {
+        return Err(ConfigError::UnsupportedVersion {
+            file: file.to_owned(),
+            version,
+        });
+    }
+
+    for migrate in &MIGRATIONS[version as usize..] {
+        migrate(root);
+    }
+
+    reader.config(&value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Result<TilemapSpriteConfig, ConfigError> {
+        parse_config(json, Path::new("test.tileset.json"))
+    }
+
+    fn invalid_path(json: &str) -> String {
+        match parse(json) {
+            Err(ConfigError::Invalid { path, .. }) => path,
+            Err(e) => panic!("wrong error {}", e),
+            Ok(_) => panic!("parsed"),
+        }
+    }
+
+    const UNVERSIONED: &str = r#"{
+        "orientations": {"2:7": {"dirs": [true, false, true, true, null, null, null, null]}},
+        "grid_width": 16,
+        "grid_height": 8,
+        "tile_width": 8,
+        "tile_height": 8
+    }"#;
+
+    #[test]
+    fn migrates_unversioned() {
+        let c = parse(UNVERSIONED).unwrap();
+
+        assert_eq!(c.version, CONFIG_VERSION);
+        assert_eq!((c.grid_width, c.grid_height), (16, 8));
+        assert_eq!((c.image_width, c.image_height), (128, 64));
+        assert_eq!((c.padding, c.margin), (0, 0));
+        assert_eq!(c.orientations[&GridCoordinate::new(2, 7)].weight, 1);
+        assert_eq!(
+            c.orientations[&GridCoordinate::new(2, 7)].dirs[1],
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let c = parse(UNVERSIONED).unwrap();
+        let json = serde_json::to_string(&c).unwrap();
+
+        assert!(json.contains(r#""version":3"#));
+        assert_eq!(parse(&json).unwrap().grid_width, 16);
+    }
+
+    #[test]
+    fn errors_name_the_path() {
+        assert_eq!(
+            invalid_path(&UNVERSIONED.replace("\"grid_width\": 16", "\"grid_width\": -1")),
+            "$.grid_width"
+        );
+        assert_eq!(
+            invalid_path(&UNVERSIONED.replace("\"tile_height\": 8", "\"tile_h\": 8")),
+            "$.tile_height"
+        );
+        assert_eq!(
+            invalid_path(&UNVERSIONED.replace("true, true, null", "true, 3, null")),
+            r#"$.orientations["2:7"].dirs[3]"#
+        );
+        assert_eq!(
+            invalid_path(&UNVERSIONED.replace("null, null]", "null]")),
+            r#"$.orientations["2:7"].dirs"#
+        );
+        assert_eq!(
+            invalid_path(&UNVERSIONED.replace("\"2:7\"", "\"2-7\"")),
+            r#"$.orientations["2-7"]"#
+        );
+        let current = serde_json::to_string(&parse(UNVERSIONED).unwrap()).unwrap();
+        assert_eq!(
+            invalid_path(&current.replace(r#""weight":1"#, r#""weight":-1"#)),
+            r#"$.orientations["2:7"].weight"#
+        );
+
+        let e = parse(&UNVERSIONED.replace("\"grid_width\": 16", "\"grid_width\": -1"))
+            .err()
+            .unwrap();
+        assert!(e
+            .to_string()
+            .starts_with("test.tileset.json: at $.grid_width: "));
+    }
+
+    #[test]
+    fn bad_files() {
+        assert!(matches!(
+            parse("{ not json"),
+            Err(ConfigError::Syntax { .. })
+        ));
+        assert_eq!(invalid_path("[]"), "$");
+        assert_eq!(invalid_path(r#"{"version": "one"}"#), "$.version");
+        assert!(matches!(
+            parse(r#"{"version": 99}"#),
+            Err(ConfigError::UnsupportedVersion { version: 99, .. })
+        ));
+    }
+}
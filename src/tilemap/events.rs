@@ -0,0 +1,24 @@
+use bevy_ecs::prelude as ecs;
+
+use super::Tile;
+
+///Sent whenever a cell of a tilemap is edited
+#[derive(Debug, Clone, Copy)]
+pub struct TileChanged {
+    ///The entity holding the edited [`super::TilemapRenderer`]
+    pub tilemap: ecs::Entity,
+    pub x: usize,
+    pub y: usize,
+    pub old: Tile,
+    ///Carries the orientation the autotiler picked, if the cell is filled
+    pub new: Tile,
+}
+
+///An edit waiting to be sent as a [`TileChanged`] once we know which entity it belongs to
+#[derive(Clone, Copy)]
+pub(super) struct PendingChange {
+    pub x: usize,
+    pub y: usize,
+    pub old: Tile,
+    pub new: Tile,
+}
@@ -0,0 +1,333 @@
+use super::{offset_in_range, Orientation, Terrain, Tile};
+
+///The tiles of a tilemap, without anything needed to draw them
+pub struct TileGrid {
+    width: usize,
+    height: usize,
+    /// Row major, `y * width + x`
+    tiles: Vec<Tile>,
+}
+
+impl TileGrid {
+    /// Create a completely filled grid
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            tiles: vec![Tile::Filled(Orientation::all()); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Attempt to offset a coordinate, returning [`None`] if it is out of bounds
+    pub fn offset(&self, x: usize, y: usize, off_x: isize, off_y: isize) -> Option<(usize, usize)> {
+        if offset_in_range(x, off_x, 0..self.width) && offset_in_range(y, off_y, 0..self.height) {
+            Some(((x as isize + off_x) as usize, (y as isize + off_y) as usize))
+        } else {
+            None
+        }
+    }
+
+    pub fn tile(&self, x: usize, y: usize) -> &Tile {
+        &self.tiles[y * self.width + x]
+    }
+
+    pub fn terrain(&self, x: usize, y: usize) -> Terrain {
+        self.tile(x, y).terrain()
+    }
+
+    pub fn get_orientation_offset_mut(
+        &mut self,
+        x: usize,
+        y: usize,
+        off_x: isize,
+        off_y: isize,
+    ) -> Option<&mut Orientation> {
+        if let Some(Tile::Filled(o)) = self.get_tile_offset_mut(x, y, off_x, off_y) {
+            Some(o)
+        } else {
+            None
+        }
+    }
+
+    pub fn remove_orientation(
+        &mut self,
+        x: usize,
+        y: usize,
+        off_x: isize,
+        off_y: isize,
+        dif: Orientation,
+    ) {
+        if let Some(o) = self.get_orientation_offset_mut(x, y, off_x, off_y) {
+            *o -= dif
+        }
+    }
+    ///Mark tile at `[x][y]` as having a new [`Tile`] adjacent
+    ///
+    ///## Returns
+    ///The change in orientation of the effected tile
+    pub fn add_orientation(
+        &mut self,
+        x: usize,
+        y: usize,
+        off_x: isize,
+        off_y: isize,
+        dif: Orientation,
+    ) -> Orientation {
+        if let Some(o) = self.get_orientation_offset_mut(x, y, off_x, off_y) {
+            o.insert(dif);
+            dif
+        } else {
+            Orientation::NONE
+        }
+    }
+
+    pub fn get_tile_offset_mut(
+        &mut self,
+        x: usize,
+        y: usize,
+        off_x: isize,
+        off_y: isize,
+    ) -> Option<&mut Tile> {
+        if let Some((x, y)) = self.offset(x, y, off_x, off_y) {
+            Some(&mut self.tiles[y * self.width + x])
+        } else {
+            None
+        }
+    }
+    pub fn get_tile_offset(&self, x: usize, y: usize, off_x: isize, off_y: isize) -> Option<&Tile> {
+        if let Some((x, y)) = self.offset(x, y, off_x, off_y) {
+            Some(&self.tiles[y * self.width + x])
+        } else {
+            None
+        }
+    }
+    pub fn tile_exists(&self, x: usize, y: usize, off_x: isize, off_y: isize) -> bool {
+        return self.get_tile_offset(x, y, off_x, off_y).is_some();
+    }
+
+    pub fn tile_filled(&self, x: usize, y: usize, off_x: isize, off_y: isize) -> bool {
+        if let Some(Tile::None) = self.get_tile_offset(x, y, off_x, off_y) {
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn update_orientation_offset(&mut self, x: usize, y: usize, off_x: isize, off_y: isize) {
+        //Reset the orientation of this tile
+        let mut orientation = Orientation::NONE;
+
+        //then build it back up by looking at if adjacent tiles exist
+        if self.tile_filled(x, y, off_x + 1, off_y) {
+            orientation |= Orientation::E;
+        }
+        if self.tile_filled(x, y, off_x - 1, off_y) {
+            orientation |= Orientation::W;
+        }
+        if self.tile_filled(x, y, off_x, off_y + 1) {
+            orientation |= Orientation::N;
+        }
+        if self.tile_filled(x, y, off_x, off_y - 1) {
+            orientation |= Orientation::S;
+        }
+        if self.tile_filled(x, y, off_x + 1, off_y - 1) {
+            orientation |= Orientation::SE;
+        }
+        if self.tile_filled(x, y, off_x - 1, off_y - 1) {
+            orientation |= Orientation::SW;
+        }
+        if self.tile_filled(x, y, off_x + 1, off_y + 1) {
+            orientation |= Orientation::NE;
+        }
+        if self.tile_filled(x, y, off_x - 1, off_y + 1) {
+            orientation |= Orientation::NW;
+        }
+
+        if let Some(o) = self.get_orientation_offset_mut(x, y, off_x, off_y) {
+            *o = orientation
+        }
+    }
+
+    ///Recalculate the orientation of every tile from `min` (inclusive) to `max` (exclusive),
+    ///clamped to the grid
+    pub fn update_orientations(&mut self, min: (usize, usize), max: (usize, usize)) {
+        for x in min.0..max.0.min(self.width) {
+            for y in min.1..max.1.min(self.height) {
+                self.update_orientation_offset(x, y, 0, 0);
+            }
+        }
+    }
+
+    ///Set what the tile at `[x][y]` is made of, without fixing any orientations
+    ///
+    ///Follow up with [`TileGrid::update_orientations`] over everything that was written to
+    ///
+    ///## Returns
+    ///If the terrain actually changed
+    pub fn write_terrain(&mut self, x: usize, y: usize, terrain: Terrain) -> bool {
+        let tile = &mut self.tiles[y * self.width + x];
+
+        if tile.terrain() == terrain {
+            false
+        } else {
+            *tile = terrain.into();
+            true
+        }
+    }
+
+    ///Set what the tile at `[x][y]` is made of, updating orientations around the tile
+    pub fn set_terrain(&mut self, x: usize, y: usize, terrain: Terrain) {
+        if self.write_terrain(x, y, terrain) {
+            //Update the orientations of all the tiles we touched
+            self.update_orientations((x.saturating_sub(1), y.saturating_sub(1)), (x + 2, y + 2));
+        }
+    }
+
+    ///Toggle the tile at `[x][y]`, updating orientations around the tiles
+    pub fn toggle(&mut self, x: usize, y: usize) {
+        let terrain = match self.terrain(x, y) {
+            Terrain::Filled => Terrain::Empty,
+            Terrain::Empty => Terrain::Filled,
+        };
+
+        self.set_terrain(x, y, terrain);
+    }
+}
+
+///Where a [`TileGrid`] sits in the world, for converting between world positions and cells
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridTransform {
+    ///World position of the bottom left corner of cell (0,0)
+    pub origin: [f32; 2],
+    ///World size of a single cell, after scaling
+    pub cell_size: [f32; 2],
+    ///Size of the grid in cells
+    pub width: usize,
+    pub height: usize,
+}
+
+impl GridTransform {
+    ///The cell containing this world position, or [`None`] if it is off the grid
+    pub fn world_to_cell(&self, world: [f32; 2]) -> Option<(usize, usize)> {
+        let x = ((world[0] - self.origin[0]) / self.cell_size[0]).floor();
+        let y = ((world[1] - self.origin[1]) / self.cell_size[1]).floor();
+
+        if x >= 0.0 && y >= 0.0 && x < self.width as f32 && y < self.height as f32 {
+            Some((x as usize, y as usize))
+        } else {
+            None
+        }
+    }
+
+    ///World position of the bottom left corner of cell `[x][y]`
+    pub fn cell_to_world(&self, x: usize, y: usize) -> [f32; 2] {
+        [
+            self.origin[0] + x as f32 * self.cell_size[0],
+            self.origin[1] + y as f32 * self.cell_size[1],
+        ]
+    }
+
+    pub fn cell_center(&self, x: usize, y: usize) -> [f32; 2] {
+        let [wx, wy] = self.cell_to_world(x, y);
+
+        [wx + self.cell_size[0] * 0.5, wy + self.cell_size[1] * 0.5]
+    }
+
+    ///Corners of cell `[x][y]`, counter clockwise from the bottom left (before any flipping scale)
+    pub fn cell_corners(&self, x: usize, y: usize) -> [[f32; 2]; 4] {
+        [
+            self.cell_to_world(x, y),
+            self.cell_to_world(x + 1, y),
+            self.cell_to_world(x + 1, y + 1),
+            self.cell_to_world(x, y + 1),
+        ]
+    }
+
+    ///World space rectangle covering cells `min` (inclusive) to `max` (exclusive), as `(min, max)`
+    pub fn cells_to_world_rect(
+        &self,
+        min: (usize, usize),
+        max: (usize, usize),
+    ) -> ([f32; 2], [f32; 2]) {
+        let a = self.cell_to_world(min.0, min.1);
+        let b = self.cell_to_world(max.0, max.1);
+
+        //a negative scale flips which corner is smallest
+        (
+            [a[0].min(b[0]), a[1].min(b[1])],
+            [a[0].max(b[0]), a[1].max(b[1])],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform() -> GridTransform {
+        GridTransform {
+            origin: [-4.0, 2.0],
+            cell_size: [0.5, 2.0],
+            width: 8,
+            height: 4,
+        }
+    }
+
+    #[test]
+    fn world_to_cell() {
+        let t = transform();
+
+        assert_eq!(t.world_to_cell([-4.0, 2.0]), Some((0, 0)));
+        assert_eq!(t.world_to_cell([-3.9, 3.9]), Some((0, 0)));
+        assert_eq!(t.world_to_cell([-2.25, 7.0]), Some((3, 2)));
+        assert_eq!(t.world_to_cell([-0.01, 9.99]), Some((7, 3)));
+
+        // Just off each edge
+        assert_eq!(t.world_to_cell([-4.01, 3.0]), None);
+        assert_eq!(t.world_to_cell([-3.0, 1.99]), None);
+        assert_eq!(t.world_to_cell([0.0, 3.0]), None);
+        assert_eq!(t.world_to_cell([-3.0, 10.0]), None);
+    }
+
+    #[test]
+    fn cell_to_world() {
+        let t = transform();
+
+        assert_eq!(t.cell_to_world(3, 2), [-2.5, 6.0]);
+        assert_eq!(t.cell_center(3, 2), [-2.25, 7.0]);
+        assert_eq!(
+            t.cell_corners(3, 2),
+            [[-2.5, 6.0], [-2.0, 6.0], [-2.0, 8.0], [-2.5, 8.0]]
+        );
+
+        for x in 0..t.width {
+            for y in 0..t.height {
+                assert_eq!(t.world_to_cell(t.cell_center(x, y)), Some((x, y)));
+            }
+        }
+    }
+
+    #[test]
+    fn flipped() {
+        let t = GridTransform {
+            cell_size: [-1.0, 1.0],
+            ..transform()
+        };
+
+        // Cells now run to the left of the origin
+        assert_eq!(t.world_to_cell([-4.5, 2.5]), Some((0, 0)));
+        assert_eq!(t.world_to_cell([-3.5, 2.5]), None);
+        assert_eq!(
+            t.cells_to_world_rect((0, 0), (2, 1)),
+            ([-6.0, 2.0], [-4.0, 3.0])
+        );
+    }
+}
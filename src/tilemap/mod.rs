@@ -1,18 +1,37 @@
 use std::ops::Range;
+pub mod ascii;
+pub mod config_history;
+pub mod config_schema;
+pub mod events;
+pub mod grid;
+pub mod pathfinding;
+pub mod png_import;
 pub mod renderer;
 pub mod sprite_config;
 pub mod sprite_config_editor;
+pub mod stamp;
 pub mod tile_requirements;
+pub mod tilemap_editor;
 
 use bitflags::bitflags;
 use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
 
 use bevy_ecs::prelude as ecs;
 
+pub use self::ascii::*;
+pub use self::config_history::*;
+pub use self::config_schema::*;
+pub use self::events::*;
+pub use self::grid::*;
+pub use self::pathfinding::*;
+pub use self::png_import::*;
 pub use self::renderer::*;
 pub use self::sprite_config::*;
 pub use self::sprite_config_editor::*;
+pub use self::stamp::*;
 pub use self::tile_requirements::*;
+pub use self::tilemap_editor::*;
 
 bitflags! {
     /// Orientation of a tile with regards to it's surroundings
@@ -60,20 +79,61 @@ impl Orientation {
     }
 }
 
-const WIDTH: usize = 16;
-const HEIGHT: usize = 16;
-
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Tile {
     Filled(Orientation),
     None,
 }
 
+/// What a [`Tile`] is made of, ignoring how it is oriented
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Terrain {
+    Empty,
+    /// Solid ground that can still be dug through
+    Filled,
+}
+
+impl Terrain {
+    pub const ALL: [Terrain; 2] = [Terrain::Empty, Terrain::Filled];
+}
+
+impl From<Terrain> for Tile {
+    /// A new tile, with its orientation still to be worked out
+    fn from(terrain: Terrain) -> Self {
+        match terrain {
+            Terrain::Filled => Tile::Filled(Orientation::NONE),
+            Terrain::Empty => Tile::None,
+        }
+    }
+}
+
+impl Tile {
+    pub fn terrain(&self) -> Terrain {
+        match self {
+            Tile::Filled(_) => Terrain::Filled,
+            Tile::None => Terrain::Empty,
+        }
+    }
+}
+
+///Sheet position of a cell with nothing in it, the vertex shader skips drawing these
+const EMPTY_TILE: u32 = u32::MAX;
+
+///GPU data for the cell at `y * grid_width + x`, which is also its instance index
 #[derive(Copy, Clone, Zeroable, Pod)]
 #[repr(C)]
-struct TileData {
+pub struct TileData {
     sheet_pos: u32,
-    grid_pos: u32,
+}
+
+///Placement of a tilemap in the world, which can change every frame so is pushed rather than stored
+#[derive(Copy, Clone, Zeroable, Pod)]
+#[repr(C)]
+pub struct TilemapPushConstants {
+    ///World position of the bottom left corner of cell (0,0)
+    origin: [f32; 2],
+    ///World size of a single cell
+    cell_size: [f32; 2],
 }
 
 #[derive(Copy, Clone, Zeroable, Pod)]
@@ -85,12 +145,36 @@ struct TilemapData {
     tile_height: f32,
     grid_width: u32,
     sheet_width: u32,
-    tiles: [TileData; WIDTH * HEIGHT],
+    ///UV distance between the starts of neighbouring tiles on the sheet
+    tile_stride: [f32; 2],
+    ///UV position of the first tile on the sheet
+    sheet_margin: [f32; 2],
 }
 
-///Tilemap system to fix any that are marked as dirty
-pub fn tilemap_on_update(mut query: ecs::Query<&mut TilemapRenderer>) {
-    query.for_each_mut(|mut tilemap| tilemap.apply_changes())
+impl TilemapData {
+    fn new(sprite: &TilemapSpriteConfig, grid_width: usize) -> Self {
+        let [tile_width, tile_height] = sprite.tile_size_uv();
+
+        Self {
+            tile_width,
+            tile_height,
+            grid_width: grid_width as u32,
+            sheet_width: sprite.grid_width(),
+            tile_stride: sprite.tile_stride_uv(),
+            sheet_margin: sprite.margin_uv(),
+        }
+    }
+}
+
+///Tilemap system to fix any that are marked as dirty, and tell everyone else what changed
+pub fn tilemap_on_update(
+    mut query: ecs::Query<(ecs::Entity, &mut TilemapRenderer)>,
+    mut changes: ecs::EventWriter<TileChanged>,
+) {
+    query.for_each_mut(|(entity, mut tilemap)| {
+        changes.send_batch(tilemap.drain_changes(entity).into_iter());
+        tilemap.apply_changes()
+    })
 }
 
 ///Does offsetting this usize place it within the range?
@@ -100,15 +184,6 @@ fn offset_in_range(i: usize, off_i: isize, range: Range<usize>) -> bool {
     v >= 0 && range.contains(&(v as usize))
 }
 
-/// Attempt to offset a coordinate, returning [`None`] if it is out of bounds
-pub fn offset(x: usize, y: usize, off_x: isize, off_y: isize) -> Option<(usize, usize)> {
-    if offset_in_range(x, off_x, 0..WIDTH) && offset_in_range(y, off_y, 0..HEIGHT) {
-        Some(((x as isize + off_x) as usize, (y as isize + off_y) as usize))
-    } else {
-        None
-    }
-}
-
 mod tilemap_fs {
     vulkano_shaders::shader! {
         ty: "fragment",
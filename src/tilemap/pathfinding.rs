@@ -0,0 +1,416 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use bevy_ecs::prelude as ecs;
+
+use super::{Terrain, TileChanged, TileGrid};
+
+/// Cost of an orthogonal step onto a tile with a terrain cost of 1
+const STRAIGHT: u32 = 10;
+/// Cost of a diagonal step onto a tile with a terrain cost of 1, roughly `STRAIGHT * sqrt(2)`
+const DIAGONAL: u32 = 14;
+
+type Cell = (usize, usize);
+
+/// Which neighbours of a cell can be stepped to directly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Only N, S, E and W
+    Four,
+    /// All 8 surrounding cells, with diagonal steps limited by the [`CornerRule`]
+    Eight(CornerRule),
+}
+
+/// When a diagonal step may pass the corner of an impassable tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CornerRule {
+    /// Diagonal steps are always allowed, even squeezing between two impassable tiles
+    CutCorners,
+    /// Diagonal steps are allowed if at least one of the two tiles beside the step is passable
+    AllowOneBlocked,
+    /// Diagonal steps are only allowed if both tiles beside the step are passable
+    NoCornerCutting,
+}
+
+/// Cost of entering a tile of each [`Terrain`], or [`None`] if it cannot be entered at all
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileCosts {
+    pub empty: Option<u32>,
+    pub filled: Option<u32>,
+}
+
+impl Default for TileCosts {
+    /// Tunnels are cheap, but solid ground can still be dug through
+    fn default() -> Self {
+        Self {
+            empty: Some(1),
+            filled: Some(4),
+        }
+    }
+}
+
+impl TileCosts {
+    pub fn cost(&self, terrain: Terrain) -> Option<u32> {
+        match terrain {
+            Terrain::Empty => self.empty,
+            Terrain::Filled => self.filled,
+        }
+    }
+
+    /// The cheapest cost of any passable terrain, used to keep the heuristic admissible
+    fn min_cost(&self) -> u32 {
+        [self.empty, self.filled]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+/// How an agent is allowed to move over a [`TileGrid`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NavRules {
+    pub connectivity: Connectivity,
+    pub costs: TileCosts,
+}
+
+impl Default for NavRules {
+    fn default() -> Self {
+        Self {
+            connectivity: Connectivity::Four,
+            costs: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    /// Every cell along the path, including the start and goal
+    pub cells: Vec<(usize, usize)>,
+    pub cost: u32,
+}
+
+struct CachedPath {
+    path: Option<Path>,
+    /// Every cell the search looked at, any change to these could change the result
+    touched: HashSet<(usize, usize)>,
+}
+
+/// A* search over a single [`TileGrid`], caching the paths it finds
+///
+/// The cache must be told about any edits to the grid with [`Pathfinder::invalidate_cell`]
+#[derive(ecs::Component)]
+pub struct Pathfinder {
+    rules: NavRules,
+    cache: HashMap<(Cell, Cell), CachedPath>,
+}
+
+impl Pathfinder {
+    pub fn new(rules: NavRules) -> Self {
+        Self {
+            rules,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn rules(&self) -> &NavRules {
+        &self.rules
+    }
+
+    /// Change how agents move, dropping every path found under the old rules
+    pub fn set_rules(&mut self, rules: NavRules) {
+        self.rules = rules;
+        self.clear();
+    }
+
+    /// Find the cheapest path from `start` to `goal`, or [`None`] if the goal cannot be reached
+    pub fn find_path(
+        &mut self,
+        grid: &TileGrid,
+        start: (usize, usize),
+        goal: (usize, usize),
+    ) -> Option<&Path> {
+        let rules = self.rules;
+
+        self.cache
+            .entry((start, goal))
+            .or_insert_with(|| search(grid, &rules, start, goal))
+            .path
+            .as_ref()
+    }
+
+    /// Forget any cached path that could have been changed by an edit to `[x][y]`
+    pub fn invalidate_cell(&mut self, x: usize, y: usize) {
+        self.cache.retain(|_, c| !c.touched.contains(&(x, y)));
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+}
+
+///Drop cached paths that ran through any edited cell of the tilemap they belong to
+pub fn pathfinding_on_tile_changed(
+    mut changes: ecs::EventReader<TileChanged>,
+    mut query: ecs::Query<&mut Pathfinder>,
+) {
+    for c in changes.iter() {
+        if let Ok(mut pathfinder) = query.get_mut(c.tilemap) {
+            // Re-tiling a neighbour only changes its orientation, which costs nothing to cross
+            let costs = pathfinder.rules().costs;
+            if costs.cost(c.old.terrain()) != costs.cost(c.new.terrain()) {
+                pathfinder.invalidate_cell(c.x, c.y);
+            }
+        }
+    }
+}
+
+fn passable(
+    grid: &TileGrid,
+    costs: &TileCosts,
+    x: usize,
+    y: usize,
+    off_x: isize,
+    off_y: isize,
+) -> bool {
+    grid.get_tile_offset(x, y, off_x, off_y)
+        .and_then(|t| costs.cost(t.terrain()))
+        .is_some()
+}
+
+/// Octile (or manhattan for 4 way movement) distance, scaled by the cheapest terrain
+fn heuristic(rules: &NavRules, a: (usize, usize), b: (usize, usize)) -> u32 {
+    let dx = (a.0 as isize - b.0 as isize).unsigned_abs() as u32;
+    let dy = (a.1 as isize - b.1 as isize).unsigned_abs() as u32;
+
+    let steps = match rules.connectivity {
+        Connectivity::Four => STRAIGHT * (dx + dy),
+        Connectivity::Eight(_) => STRAIGHT * dx.max(dy) + (DIAGONAL - STRAIGHT) * dx.min(dy),
+    };
+
+    steps * rules.costs.min_cost()
+}
+
+fn search(
+    grid: &TileGrid,
+    rules: &NavRules,
+    start: (usize, usize),
+    goal: (usize, usize),
+) -> CachedPath {
+    let mut touched = HashSet::new();
+    touched.insert(start);
+    touched.insert(goal);
+
+    let in_bounds = |(x, y): (usize, usize)| x < grid.width() && y < grid.height();
+
+    if !in_bounds(start) || !in_bounds(goal) || !passable(grid, &rules.costs, goal.0, goal.1, 0, 0)
+    {
+        return CachedPath {
+            path: None,
+            touched,
+        };
+    }
+
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<(usize, usize), u32> = HashMap::new();
+    let mut came_from: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+
+    best.insert(start, 0);
+    open.push(Reverse((heuristic(rules, start, goal), 0, start)));
+
+    while let Some(Reverse((_, g, cell))) = open.pop() {
+        if cell == goal {
+            let mut cells = vec![goal];
+            let mut c = goal;
+            while let Some(&prev) = came_from.get(&c) {
+                cells.push(prev);
+                c = prev;
+            }
+            cells.reverse();
+
+            return CachedPath {
+                path: Some(Path { cells, cost: g }),
+                touched,
+            };
+        }
+
+        // Stale entry, a cheaper route here has already been expanded
+        if g > best[&cell] {
+            continue;
+        }
+
+        let (x, y) = cell;
+
+        for off_x in -1..=1 {
+            for off_y in -1..=1 {
+                let diagonal = off_x != 0 && off_y != 0;
+
+                let step = match (rules.connectivity, off_x, off_y) {
+                    (_, 0, 0) => continue,
+                    (Connectivity::Four, _, _) if diagonal => continue,
+                    (Connectivity::Eight(corners), _, _) if diagonal => {
+                        let beside = [
+                            passable(grid, &rules.costs, x, y, off_x, 0),
+                            passable(grid, &rules.costs, x, y, 0, off_y),
+                        ];
+                        let allowed = match corners {
+                            CornerRule::CutCorners => true,
+                            CornerRule::AllowOneBlocked => beside[0] || beside[1],
+                            CornerRule::NoCornerCutting => beside[0] && beside[1],
+                        };
+                        if !allowed {
+                            continue;
+                        }
+                        DIAGONAL
+                    }
+                    _ => STRAIGHT,
+                };
+
+                let next = match grid.offset(x, y, off_x, off_y) {
+                    Some(n) => n,
+                    None => continue,
+                };
+
+                touched.insert(next);
+
+                let cost = match rules.costs.cost(grid.tile(next.0, next.1).terrain()) {
+                    Some(c) => c,
+                    None => continue,
+                };
+
+                let next_g = g + step * cost;
+
+                if !matches!(best.get(&next), Some(&b) if b <= next_g) {
+                    best.insert(next, next_g);
+                    came_from.insert(next, cell);
+                    open.push(Reverse((
+                        next_g + heuristic(rules, next, goal),
+                        next_g,
+                        next,
+                    )));
+                }
+            }
+        }
+    }
+
+    CachedPath {
+        path: None,
+        touched,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5x5 grid that is solid apart from a tunnel along the bottom row and up the right side
+    fn tunnel() -> TileGrid {
+        let mut g = TileGrid::new(5, 5);
+        for x in 0..5 {
+            g.toggle(x, 0);
+        }
+        for y in 1..5 {
+            g.toggle(4, y);
+        }
+        g
+    }
+
+    fn tunnels_only() -> NavRules {
+        NavRules {
+            connectivity: Connectivity::Four,
+            costs: TileCosts {
+                empty: Some(1),
+                filled: None,
+            },
+        }
+    }
+
+    #[test]
+    fn follows_tunnel() {
+        let g = tunnel();
+        let mut p = Pathfinder::new(tunnels_only());
+
+        let path = p.find_path(&g, (0, 0), (4, 4)).unwrap();
+
+        assert_eq!(path.cells.len(), 9);
+        assert_eq!(path.cost, 8 * STRAIGHT);
+        assert!(path.cells.iter().all(|&(x, y)| y == 0 || x == 4));
+    }
+
+    #[test]
+    fn digs_when_cheaper() {
+        let g = tunnel();
+        let mut p = Pathfinder::new(NavRules {
+            connectivity: Connectivity::Four,
+            costs: TileCosts {
+                empty: Some(1),
+                filled: Some(2),
+            },
+        });
+
+        // Going round the tunnel is cheaper than digging across
+        let path = p.find_path(&g, (0, 0), (4, 4)).unwrap();
+        assert_eq!(path.cost, 8 * STRAIGHT);
+
+        // But digging straight up beats going round and digging back
+        let path = p.find_path(&g, (0, 0), (0, 4)).unwrap();
+        assert_eq!(path.cost, 4 * 2 * STRAIGHT);
+        assert!(path.cells.iter().all(|&(x, _)| x == 0));
+    }
+
+    #[test]
+    fn unreachable() {
+        let g = tunnel();
+        let mut p = Pathfinder::new(tunnels_only());
+
+        assert!(p.find_path(&g, (0, 0), (2, 2)).is_none());
+    }
+
+    #[test]
+    fn corner_rules() {
+        // Two open cells touching only at a corner
+        let mut g = TileGrid::new(2, 2);
+        g.toggle(0, 0);
+        g.toggle(1, 1);
+
+        let rules = |corners| NavRules {
+            connectivity: Connectivity::Eight(corners),
+            ..tunnels_only()
+        };
+
+        let mut cut = Pathfinder::new(rules(CornerRule::CutCorners));
+        assert_eq!(cut.find_path(&g, (0, 0), (1, 1)).unwrap().cost, DIAGONAL);
+
+        let mut one = Pathfinder::new(rules(CornerRule::AllowOneBlocked));
+        assert!(one.find_path(&g, (0, 0), (1, 1)).is_none());
+
+        g.toggle(1, 0);
+        let mut one = Pathfinder::new(rules(CornerRule::AllowOneBlocked));
+        assert_eq!(one.find_path(&g, (0, 0), (1, 1)).unwrap().cost, DIAGONAL);
+
+        let mut none = Pathfinder::new(rules(CornerRule::NoCornerCutting));
+        assert_eq!(
+            none.find_path(&g, (0, 0), (1, 1)).unwrap().cost,
+            2 * STRAIGHT
+        );
+    }
+
+    #[test]
+    fn cache_invalidation() {
+        let mut g = tunnel();
+        let mut p = Pathfinder::new(tunnels_only());
+
+        assert!(p.find_path(&g, (0, 0), (2, 2)).is_none());
+
+        // Dig down to the goal, the cached result is stale until invalidated
+        g.toggle(2, 1);
+        g.toggle(2, 2);
+        assert!(p.find_path(&g, (0, 0), (2, 2)).is_none());
+
+        p.invalidate_cell(2, 1);
+        p.invalidate_cell(2, 2);
+        assert_eq!(p.find_path(&g, (0, 0), (2, 2)).unwrap().cells.len(), 5);
+    }
+}
@@ -0,0 +1,240 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    str::FromStr,
+};
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use super::{MergeMode, Terrain, TileGrid, TileStamp};
+
+/// An RGBA colour, written as `#rrggbb` or `#rrggbbaa` in palette files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Colour(pub [u8; 4]);
+
+impl FromStr for Colour {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s
+            .strip_prefix('#')
+            .filter(|h| (h.len() == 6 || h.len() == 8) && h.is_ascii())
+            .ok_or_else(|| format!("`{}` isn't a #rrggbb or #rrggbbaa colour", s))?;
+
+        let mut c = [255; 4];
+        for (i, channel) in c.iter_mut().enumerate().take(hex.len() / 2) {
+            *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| format!("`{}` isn't a #rrggbb or #rrggbbaa colour", s))?;
+        }
+
+        Ok(Self(c))
+    }
+}
+
+impl TryFrom<String> for Colour {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Colour> for String {
+    fn from(c: Colour) -> Self {
+        c.to_string()
+    }
+}
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r, g, b, a] = self.0;
+
+        if a == 255 {
+            write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+    }
+}
+
+/// Which [`Terrain`] each pixel colour of a level sketch stands for
+///
+/// ```json
+/// { "colours": { "#000000": "Filled", "#ffffff": "Empty" } }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Palette {
+    pub colours: HashMap<Colour, Terrain>,
+}
+
+impl Palette {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ImportError> {
+        let file = File::open(path)?;
+
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn terrain(&self, colour: Colour) -> Option<Terrain> {
+        self.colours.get(&colour).copied()
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    Palette(serde_json::Error),
+    Image(image::ImageError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "{}", e),
+            ImportError::Palette(e) => write!(f, "bad palette: {}", e),
+            ImportError::Image(e) => write!(f, "bad image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(e: serde_json::Error) -> Self {
+        ImportError::Palette(e)
+    }
+}
+
+impl From<image::ImageError> for ImportError {
+    fn from(e: image::ImageError) -> Self {
+        ImportError::Image(e)
+    }
+}
+
+/// A colour in the image that the palette doesn't know about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownColour {
+    pub colour: Colour,
+    /// How many pixels used it
+    pub count: usize,
+    /// The first cell it was found in, to help track it down
+    pub first: (usize, usize),
+}
+
+pub struct ImportedMap {
+    pub grid: TileGrid,
+    /// Cells with an unknown colour are left empty
+    pub unknown: Vec<UnknownColour>,
+}
+
+impl ImportedMap {
+    /// The whole map, ready to be pasted into a tilemap
+    pub fn stamp(&self) -> TileStamp {
+        TileStamp::capture(&self.grid, (0, 0), (self.grid.width(), self.grid.height()))
+    }
+}
+
+/// Fill a grid from `image`, one cell per pixel
+///
+/// The top row of pixels becomes the top row of the grid, so the map isn't upside down
+pub fn import_image(image: &RgbaImage, palette: &Palette) -> ImportedMap {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+
+    let mut stamp = TileStamp::new(width, height, Terrain::Empty);
+    let mut unknown: Vec<UnknownColour> = Vec::new();
+
+    for (px, py, pixel) in image.enumerate_pixels() {
+        let colour = Colour(pixel.0);
+        let (x, y) = (px as usize, height - 1 - py as usize);
+
+        match palette.terrain(colour) {
+            Some(terrain) => stamp.set_terrain(x, y, terrain),
+            None => match unknown.iter_mut().find(|u| u.colour == colour) {
+                Some(u) => u.count += 1,
+                None => unknown.push(UnknownColour {
+                    colour,
+                    count: 1,
+                    first: (x, y),
+                }),
+            },
+        }
+    }
+
+    let mut grid = TileGrid::new(width, height);
+    stamp.paste(&mut grid, 0, 0, MergeMode::Overwrite);
+
+    ImportedMap { grid, unknown }
+}
+
+pub fn import_png(path: impl AsRef<Path>, palette: &Palette) -> Result<ImportedMap, ImportError> {
+    let image = image::open(path)?.to_rgba8();
+
+    Ok(import_image(&image, palette))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    fn palette() -> Palette {
+        serde_json::from_str(r##"{ "colours": { "#000000": "Filled", "#ffffffff": "Empty" } }"##)
+            .unwrap()
+    }
+
+    #[test]
+    fn colours() {
+        assert_eq!("#ff8000".parse(), Ok(Colour([255, 128, 0, 255])));
+        assert_eq!("#ff800040".parse(), Ok(Colour([255, 128, 0, 64])));
+        assert!("ff8000".parse::<Colour>().is_err());
+        assert!("#ff80".parse::<Colour>().is_err());
+        assert!("#gg8000".parse::<Colour>().is_err());
+
+        assert_eq!(Colour([255, 128, 0, 255]).to_string(), "#ff8000");
+        assert_eq!(Colour([255, 128, 0, 64]).to_string(), "#ff800040");
+
+        assert_eq!(palette().terrain(Colour(WHITE)), Some(Terrain::Empty));
+    }
+
+    #[test]
+    fn import() {
+        // A tunnel along the top row, and two stray red pixels
+        let image = RgbaImage::from_fn(3, 2, |x, y| match (x, y) {
+            (_, 0) => Rgba(WHITE),
+            (2, 1) => Rgba(BLACK),
+            _ => Rgba(RED),
+        });
+
+        let map = import_image(&image, &palette());
+
+        assert_eq!((map.grid.width(), map.grid.height()), (3, 2));
+        assert_eq!(map.grid.terrain(0, 1), Terrain::Empty);
+        assert_eq!(map.grid.terrain(2, 1), Terrain::Empty);
+        assert_eq!(map.grid.terrain(2, 0), Terrain::Filled);
+        // Unknown colours are left empty
+        assert_eq!(map.grid.terrain(0, 0), Terrain::Empty);
+
+        assert_eq!(
+            map.unknown,
+            vec![UnknownColour {
+                colour: Colour(RED),
+                count: 2,
+                first: (0, 0),
+            }]
+        );
+    }
+}
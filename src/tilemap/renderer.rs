@@ -1,7 +1,15 @@
-use crate::{engine, texture::Texture, uniform::Transformations};
+use crate::{
+    engine,
+    texture::Texture,
+    transform::{Position, Scale},
+    uniform::Transformations,
+};
 
 use rand::Rng;
-use std::sync::{Arc, Mutex};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
 use vulkano::{
     buffer::{BufferUsage, CpuAccessibleBuffer},
     descriptor_set::WriteDescriptorSet,
@@ -11,17 +19,156 @@ use vulkano::{
 use bevy_ecs::prelude as ecs;
 
 use super::{
-    offset, tilemap_fs, tilemap_vs, Orientation, Tile, TileData, TilemapData, TilemapSpriteConfig,
-    HEIGHT, WIDTH,
+    tilemap_fs, tilemap_vs, GridTransform, MergeMode, PendingChange, Tile, TileChanged, TileData,
+    TileGrid, TileStamp, TilemapData, TilemapPushConstants, TilemapSpriteConfig, EMPTY_TILE,
 };
 
+///Rectangle of cells whose GPU data is out of date, `min` inclusive and `max` exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRegion {
+    pub min: (usize, usize),
+    pub max: (usize, usize),
+}
+
+impl DirtyRegion {
+    ///Every cell of a `width` by `height` grid
+    pub fn all(width: usize, height: usize) -> Self {
+        Self {
+            min: (0, 0),
+            max: (width, height),
+        }
+    }
+
+    ///The cells within one step of `[x][y]`, as a toggle changes their orientations too
+    pub fn around(grid: &TileGrid, x: usize, y: usize) -> Self {
+        Self::around_rect(grid, (x, y), (x + 1, y + 1))
+    }
+
+    ///The cells from `min` to `max`, plus a border of one cell for the orientations that changed
+    ///
+    ///Clamped to the grid, so a rectangle entirely off it gives an empty region
+    pub fn around_rect(grid: &TileGrid, min: (usize, usize), max: (usize, usize)) -> Self {
+        let max = (
+            max.0.saturating_add(1).min(grid.width()),
+            max.1.saturating_add(1).min(grid.height()),
+        );
+
+        Self {
+            min: (
+                min.0.saturating_sub(1).min(max.0),
+                min.1.saturating_sub(1).min(max.1),
+            ),
+            max,
+        }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min: (self.min.0.min(other.min.0), self.min.1.min(other.min.1)),
+            max: (self.max.0.max(other.max.0), self.max.1.max(other.max.1)),
+        }
+    }
+}
+
+///Where on the sheet to draw this tile from, [`EMPTY_TILE`] if nothing should be drawn
+fn sheet_pos(sprite: &TilemapSpriteConfig, tile: Tile) -> u32 {
+    match tile {
+        Tile::Filled(o) => match sprite.find_tile_index(o) {
+            Some(gc) => (gc.x + gc.y * sprite.grid_width) as u32,
+            //FIXME: Better error texture for invalid sprites
+            None => 17,
+        },
+        Tile::None => EMPTY_TILE,
+    }
+}
+
+///Re-resolve the sprites of every cell in `region`, writing them into their slots in `tiles`
+///
+///`tiles` holds one slot per cell of the grid, at `y * width + x`
+pub fn resolve_region(
+    grid: &TileGrid,
+    sprite: &TilemapSpriteConfig,
+    region: DirtyRegion,
+    tiles: &mut [TileData],
+) {
+    let (min_x, min_y) = region.min;
+    let (max_x, max_y) = region.max;
+
+    for y in min_y..max_y {
+        let row = y * grid.width();
+
+        for (x, slot) in (min_x..max_x).zip(&mut tiles[row + min_x..row + max_x]) {
+            slot.sheet_pos = sheet_pos(sprite, *grid.tile(x, y));
+        }
+    }
+}
+
+///The cells of a [`TilemapRenderer`], with everything edited since they were last uploaded
+///
+///Kept apart from the GPU buffers, so edits can be checked without a device
+struct TileEdits {
+    grid: TileGrid,
+    dirty: Option<DirtyRegion>,
+    changes: Vec<PendingChange>,
+}
+
+impl TileEdits {
+    ///Nothing has been uploaded yet, so every cell starts out dirty
+    fn new(grid: TileGrid) -> Self {
+        Self {
+            dirty: Some(DirtyRegion::all(grid.width(), grid.height())),
+            grid,
+            changes: Vec::new(),
+        }
+    }
+
+    fn toggle(&mut self, x: usize, y: usize) {
+        let old = *self.grid.tile(x, y);
+
+        self.grid.toggle(x, y);
+
+        let new = *self.grid.tile(x, y);
+
+        self.changes.push(PendingChange { x, y, old, new });
+
+        self.mark_dirty(DirtyRegion::around(&self.grid, x, y));
+    }
+
+    fn paste(&mut self, stamp: &TileStamp, x: usize, y: usize, mode: MergeMode) {
+        for (x, y, old) in stamp.paste(&mut self.grid, x, y, mode) {
+            let new = *self.grid.tile(x, y);
+
+            self.changes.push(PendingChange { x, y, old, new });
+        }
+
+        self.mark_dirty(DirtyRegion::around_rect(
+            &self.grid,
+            (x, y),
+            (
+                x.saturating_add(stamp.width()),
+                y.saturating_add(stamp.height()),
+            ),
+        ));
+    }
+
+    fn mark_dirty(&mut self, region: DirtyRegion) {
+        self.dirty = Some(self.dirty.map_or(region, |d| d.union(region)));
+    }
+}
+
 #[derive(ecs::Component)]
 pub struct TilemapRenderer {
-    tiles: [[Tile; HEIGHT]; WIDTH],
-    dirty: bool,
-    instance_count: u32,
+    edits: TileEdits,
+    ///Size of a cell in world units, before the entity's [`Scale`]
+    cell_size: f32,
     sprite: Arc<Mutex<TilemapSpriteConfig>>,
+    ///[`TilemapSpriteConfig::revision`] when the sheet layout was last uploaded
+    sheet_revision: u64,
+    ///Checked every frame, so the config is only locked when there is something to upload
+    sheet_revision_counter: Arc<AtomicU64>,
     map_buffer: Arc<CpuAccessibleBuffer<TilemapData>>,
+    ///One slot for every cell, so edits only need to touch the cells around them
+    tile_buffer: Arc<CpuAccessibleBuffer<[TileData]>>,
 
     //the actual GPU texture reference for the texture
     texture: Texture<StorageImage>,
@@ -31,235 +178,175 @@ impl TilemapRenderer {
     pub fn new(
         sprite: Arc<Mutex<TilemapSpriteConfig>>,
         texture: Texture<StorageImage>,
+        width: usize,
+        height: usize,
         engine: &engine::Engine,
     ) -> Self {
-        let map_buffer = {
-            let sprite_lock = sprite.lock().unwrap();
+        let mut grid = TileGrid::new(width, height);
+
+        let mut rng = rand::thread_rng();
+
+        for x in 0..width {
+            for y in 0..height {
+                if rng.gen_bool(0.1) {
+                    grid.toggle(x, y);
+                }
+            }
+        }
+
+        Self::from_grid(sprite, texture, grid, engine)
+    }
 
-            let [tile_width, tile_height] = sprite_lock.tile_size_uv();
+    ///Draw an existing grid, such as one from [`import_png`](super::import_png)
+    pub fn from_grid(
+        sprite: Arc<Mutex<TilemapSpriteConfig>>,
+        texture: Texture<StorageImage>,
+        grid: TileGrid,
+        engine: &engine::Engine,
+    ) -> Self {
+        let (width, height) = (grid.width(), grid.height());
 
-            CpuAccessibleBuffer::from_data(
+        let (map_buffer, sheet_revision, sheet_revision_counter) = {
+            let sprite_lock = sprite.lock().unwrap();
+
+            let buffer = CpuAccessibleBuffer::from_data(
                 engine.device(),
                 BufferUsage::all(), //TODO: this should be more specific?
                 false,
-                TilemapData {
-                    tile_width,
-                    tile_height,
-                    sheet_width: sprite_lock.grid_width(),
-                    grid_width: 16,
-                    tiles: [TileData {
-                        sheet_pos: 0,
-                        grid_pos: 0,
-                    }; 16 * 16],
-                },
+                TilemapData::new(&sprite_lock, width),
+            )
+            .expect("failed to create buffer");
+
+            (
+                buffer,
+                sprite_lock.revision(),
+                sprite_lock.revision_counter(),
             )
-            .expect("failed to create buffer")
         };
 
-        let mut rng = rand::thread_rng();
+        let tile_buffer = CpuAccessibleBuffer::from_iter(
+            engine.device(),
+            BufferUsage::all(), //TODO: this should be more specific?
+            false,
+            (0..width * height).map(|_| TileData {
+                sheet_pos: EMPTY_TILE,
+            }),
+        )
+        .expect("failed to create buffer");
 
         let mut s = Self {
-            tiles: [[Tile::Filled(Orientation::all()); 16]; 16],
+            edits: TileEdits::new(grid),
+            cell_size: 1.0,
             sprite,
-            instance_count: 0,
             texture,
-            dirty: true,
+            sheet_revision,
+            sheet_revision_counter,
             map_buffer,
+            tile_buffer,
         };
 
-        for x in 0..16 {
-            for y in 0..16 {
-                if rng.gen_bool(0.1) {
-                    s.toggle(x, y);
-                }
-            }
-        }
-
         s.apply_changes();
 
         s
     }
 
-    fn get_sheet_pos(&self, o: Orientation) -> u32 {
-        let t = self.sprite.try_lock().unwrap().find_tile_index(o);
+    ///Upload the cells that have changed since the last call
+    ///
+    ///If the sheet's rules or layout have changed, everything is redrawn
+    pub fn apply_changes(&mut self) {
+        let revision = self.sheet_revision_counter.load(Ordering::Relaxed);
 
-        if let Some(gc) = t {
-            let (x, y) = gc.into();
-            (x + y * 16) as u32
-        } else {
-            //FIXME: Better error texture for invalid sprites
-            17
+        if revision == self.sheet_revision && self.edits.dirty.is_none() {
+            return;
         }
-    }
-
-    pub fn apply_changes(&mut self) {
-        //TODO: this would appear in some kind of update function every frame
-
-        if self.dirty {
-            let mut tiles = [TileData {
-                sheet_pos: 0,
-                grid_pos: 0,
-            }; 16 * 16];
-
-            let mut i = 0;
-
-            for x in 0..16 {
-                for y in 0..16 {
-                    if let Tile::Filled(o) = self.tiles[x][y] {
-                        tiles[i] = TileData {
-                            grid_pos: (y * 16 + x) as u32,
-                            sheet_pos: self.get_sheet_pos(o),
-                        };
-                        i += 1;
-                    }
-                }
-            }
 
-            let mut w = self.map_buffer.write().unwrap();
+        let sprite = self.sprite.lock().unwrap();
 
-            (*w).tiles = tiles;
+        if sprite.revision() != self.sheet_revision {
+            self.sheet_revision = sprite.revision();
 
-            self.instance_count = i as u32;
+            let grid = &self.edits.grid;
+            *self.map_buffer.write().unwrap() = TilemapData::new(&sprite, grid.width());
+            self.edits.dirty = Some(DirtyRegion::all(grid.width(), grid.height()));
         }
 
-        self.dirty = false;
+        if let Some(region) = self.edits.dirty.take() {
+            let mut w = self.tile_buffer.write().unwrap();
+
+            resolve_region(&self.edits.grid, &sprite, region, &mut w);
+        }
     }
 
     pub fn tile(&self, x: usize, y: usize) -> &Tile {
-        &self.tiles[x][y]
+        self.edits.grid.tile(x, y)
     }
 
-    pub fn get_orientation_offset_mut(
-        &mut self,
-        x: usize,
-        y: usize,
-        off_x: isize,
-        off_y: isize,
-    ) -> Option<&mut Orientation> {
-        if let Some(Tile::Filled(o)) = self.get_tile_offset_mut(x, y, off_x, off_y) {
-            Some(o)
-        } else {
-            None
-        }
+    pub fn grid(&self) -> &TileGrid {
+        &self.edits.grid
     }
 
-    pub fn remove_orientation(
-        &mut self,
-        x: usize,
-        y: usize,
-        off_x: isize,
-        off_y: isize,
-        dif: Orientation,
-    ) {
-        if let Some(o) = self.get_orientation_offset_mut(x, y, off_x, off_y) {
-            *o -= dif
-        }
-    }
-    ///Mark tile at `[x][y]` as having a new [`Tile`] adjacent
-    ///
-    ///## Returns
-    ///The change in orientation of the effected tile
-    pub fn add_orientation(
-        &mut self,
-        x: usize,
-        y: usize,
-        off_x: isize,
-        off_y: isize,
-        dif: Orientation,
-    ) -> Orientation {
-        if let Some(o) = self.get_orientation_offset_mut(x, y, off_x, off_y) {
-            o.insert(dif);
-            dif
-        } else {
-            Orientation::NONE
-        }
+    ///Toggle the tile at `[x][y]`, updating orientations around the tiles
+    pub fn toggle(&mut self, x: usize, y: usize) {
+        self.edits.toggle(x, y);
     }
 
-    pub fn get_tile_offset_mut(
-        &mut self,
-        x: usize,
-        y: usize,
-        off_x: isize,
-        off_y: isize,
-    ) -> Option<&mut Tile> {
-        if let Some((x, y)) = offset(x, y, off_x, off_y) {
-            Some(&mut self.tiles[x][y])
-        } else {
-            None
-        }
+    ///Paste `stamp` with its bottom left corner at `[x][y]`
+    pub fn paste(&mut self, stamp: &TileStamp, x: usize, y: usize, mode: MergeMode) {
+        self.edits.paste(stamp, x, y, mode);
     }
-    pub fn get_tile_offset(&self, x: usize, y: usize, off_x: isize, off_y: isize) -> Option<&Tile> {
-        if let Some((x, y)) = offset(x, y, off_x, off_y) {
-            Some(&self.tiles[x][y])
-        } else {
-            None
-        }
-    }
-    pub fn tile_exists(&self, x: usize, y: usize, off_x: isize, off_y: isize) -> bool {
-        return self.get_tile_offset(x, y, off_x, off_y).is_some();
+
+    ///Take every edit made since the last call, as events coming from `entity`
+    pub fn drain_changes(&mut self, entity: ecs::Entity) -> Vec<TileChanged> {
+        self.edits
+            .changes
+            .drain(..)
+            .map(|c| TileChanged {
+                tilemap: entity,
+                x: c.x,
+                y: c.y,
+                old: c.old,
+                new: c.new,
+            })
+            .collect()
     }
 
-    pub fn tile_filled(&self, x: usize, y: usize, off_x: isize, off_y: isize) -> bool {
-        if let Some(Tile::None) = self.get_tile_offset(x, y, off_x, off_y) {
-            false
-        } else {
-            true
-        }
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
     }
 
-    pub fn update_orientation_offset(&mut self, x: usize, y: usize, off_x: isize, off_y: isize) {
-        //Reset the orientation of this tile
-        let mut orientation = Orientation::NONE;
+    pub fn set_cell_size(&mut self, cell_size: f32) {
+        self.cell_size = cell_size;
+    }
 
-        //then build it back up by looking at if adjacent tiles exist
-        if self.tile_filled(x, y, off_x + 1, off_y) {
-            orientation |= Orientation::E;
-        }
-        if self.tile_filled(x, y, off_x - 1, off_y) {
-            orientation |= Orientation::W;
-        }
-        if self.tile_filled(x, y, off_x, off_y + 1) {
-            orientation |= Orientation::N;
-        }
-        if self.tile_filled(x, y, off_x, off_y - 1) {
-            orientation |= Orientation::S;
-        }
-        if self.tile_filled(x, y, off_x + 1, off_y - 1) {
-            orientation |= Orientation::SE;
-        }
-        if self.tile_filled(x, y, off_x - 1, off_y - 1) {
-            orientation |= Orientation::SW;
-        }
-        if self.tile_filled(x, y, off_x + 1, off_y + 1) {
-            orientation |= Orientation::NE;
-        }
-        if self.tile_filled(x, y, off_x - 1, off_y + 1) {
-            orientation |= Orientation::NW;
-        }
+    ///Where this tilemap's cells are in the world, given the components of its entity
+    pub fn transform(&self, pos: &Position, scale: Option<&Scale>) -> GridTransform {
+        let Scale(scale_x, scale_y) = scale.copied().unwrap_or_default();
 
-        if let Some(o) = self.get_orientation_offset_mut(x, y, off_x, off_y) {
-            *o = orientation
+        GridTransform {
+            origin: [pos.0, pos.1],
+            cell_size: [self.cell_size * scale_x, self.cell_size * scale_y],
+            width: self.edits.grid.width(),
+            height: self.edits.grid.height(),
         }
     }
 
-    ///Toggle the tile at `[x][y]`, updating orientations around the tiles
-    pub fn toggle(&mut self, x: usize, y: usize) {
-        self.tiles[x][y] = match self.tiles[x][y] {
-            Tile::Filled(_) => Tile::None,
-            Tile::None => Tile::Filled(Orientation::NONE),
-        };
-        //Update the orientations of all the tiles we touched
-        for off_x in -1..=1 {
-            for off_y in -1..=1 {
-                self.update_orientation_offset(x, y, off_x, off_y);
-            }
+    /// Generate push constants from context
+    pub fn get_push_constants(
+        &self,
+        pos: &Position,
+        scale: Option<&Scale>,
+    ) -> TilemapPushConstants {
+        let t = self.transform(pos, scale);
+
+        TilemapPushConstants {
+            origin: t.origin,
+            cell_size: t.cell_size,
         }
-
-        self.dirty = true;
     }
 
+    ///Every cell gets an instance, empty ones are culled by the vertex shader
     pub fn instance_count(&self) -> u32 {
-        self.instance_count
+        (self.edits.grid.width() * self.edits.grid.height()) as u32
     }
 
     pub fn create_material(
@@ -270,14 +357,172 @@ impl TilemapRenderer {
         let vs = tilemap_vs::load(engine.device()).unwrap();
         let fs = tilemap_fs::load(engine.device()).unwrap();
 
-        engine.create_material(
-            vs,
-            fs,
-            [
-                WriteDescriptorSet::buffer(0, globals.get_buffer()),
-                WriteDescriptorSet::buffer(1, self.map_buffer.clone()),
-                self.texture.describe(3),
-            ],
-        )
+        engine.create_material(vs, fs, Default::default(), self.descriptors(globals))
+    }
+
+    ///Draw from a new sheet, such as after it changed on disk, rebuilding `material`'s
+    ///descriptors to match
+    pub fn set_texture(
+        &mut self,
+        texture: Texture<StorageImage>,
+        engine: &mut engine::Engine,
+        material: &engine::MatID,
+        globals: &Transformations,
+    ) {
+        self.texture = texture;
+
+        engine.update_material(material, self.descriptors(globals));
+    }
+
+    fn descriptors(&self, globals: &Transformations) -> [WriteDescriptorSet; 4] {
+        [
+            WriteDescriptorSet::buffer(0, globals.get_buffer()),
+            WriteDescriptorSet::buffer(1, self.map_buffer.clone()),
+            WriteDescriptorSet::buffer(2, self.tile_buffer.clone()),
+            self.texture.describe(3),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::{Terrain, TileRequirements};
+    use std::time::Instant;
+
+    /// A sheet where every orientation is drawn with the tile at (2, 1)
+    fn config() -> TilemapSpriteConfig {
+        let mut c = TilemapSpriteConfig::new(16, 8);
+        c.orientations
+            .insert((2, 1).into(), TileRequirements::default());
+        c.sync_coordinates();
+        c
+    }
+
+    #[test]
+    fn resolve_only_region() {
+        let mut grid = TileGrid::new(8, 8);
+        let sprite = config();
+        let mut tiles = vec![TileData { sheet_pos: 0 }; 8 * 8];
+
+        grid.toggle(4, 4);
+        resolve_region(&grid, &sprite, DirtyRegion::around(&grid, 4, 4), &mut tiles);
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let expected = match (x, y) {
+                    (4, 4) => EMPTY_TILE,
+                    (3..=5, 3..=5) => 2 + 16,
+                    // Outside the region, so never written
+                    _ => 0,
+                };
+                assert_eq!(tiles[y * 8 + x].sheet_pos, expected, "cell {},{}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn edits_upload_only_their_region() {
+        const SIZE: usize = 256;
+        //Never a real sheet position, so any slot still holding it wasn't written
+        const UNTOUCHED: u32 = 12345;
+
+        let sprite = config();
+        let mut tiles = vec![
+            TileData {
+                sheet_pos: UNTOUCHED
+            };
+            SIZE * SIZE
+        ];
+
+        let mut edits = TileEdits::new(TileGrid::new(SIZE, SIZE));
+        //The first upload is everything, this test is about the ones after it
+        edits.dirty = None;
+
+        //Edits between uploads build up into one region
+        edits.toggle(10, 20);
+        edits.toggle(12, 21);
+        edits.paste(
+            &TileStamp::new(2, 2, Terrain::Empty),
+            11,
+            25,
+            MergeMode::Overwrite,
+        );
+
+        let region = edits.dirty.take().unwrap();
+        assert_eq!((region.min, region.max), ((9, 19), (14, 28)));
+        assert_eq!(edits.changes.len(), 2 + 2 * 2);
+
+        resolve_region(&edits.grid, &sprite, region, &mut tiles);
+
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let inside = (9..14).contains(&x) && (19..28).contains(&y);
+                let written = tiles[y * SIZE + x].sheet_pos != UNTOUCHED;
+
+                assert_eq!(written, inside, "cell {},{}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn paste_off_grid() {
+        let sprite = config();
+        let mut tiles = vec![TileData { sheet_pos: 0 }; 8 * 8];
+
+        let mut edits = TileEdits::new(TileGrid::new(8, 8));
+        edits.dirty = None;
+
+        let stamp = TileStamp::new(2, 2, Terrain::Empty);
+        edits.paste(&stamp, 12, 3, MergeMode::Overwrite);
+        edits.paste(&stamp, 3, 20, MergeMode::Overwrite);
+        edits.paste(&stamp, usize::MAX, usize::MAX, MergeMode::Overwrite);
+
+        assert!(edits.changes.is_empty());
+
+        let region = edits.dirty.take().unwrap();
+        resolve_region(&edits.grid, &sprite, region, &mut tiles);
+
+        // Hanging off the edge still retiles the cells that landed on the grid
+        edits.paste(&stamp, 7, 7, MergeMode::Overwrite);
+        assert_eq!(edits.changes.len(), 1);
+        assert_eq!(
+            edits.dirty,
+            Some(DirtyRegion {
+                min: (6, 6),
+                max: (8, 8)
+            })
+        );
+    }
+
+    /// Run with `cargo test --release bench_edit_large_map -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_edit_large_map() {
+        const SIZE: usize = 1024;
+        const EDITS: usize = 1000;
+
+        let sprite = config();
+        let mut tiles = vec![TileData { sheet_pos: 0 }; SIZE * SIZE];
+        let mut edits = TileEdits::new(TileGrid::new(SIZE, SIZE));
+
+        let start = Instant::now();
+        let region = edits.dirty.take().unwrap();
+        resolve_region(&edits.grid, &sprite, region, &mut tiles);
+        let full = start.elapsed();
+
+        let start = Instant::now();
+        for i in 0..EDITS {
+            edits.toggle(i * 7 % SIZE, i * 13 % SIZE);
+
+            let region = edits.dirty.take().unwrap();
+            resolve_region(&edits.grid, &sprite, region, &mut tiles);
+        }
+        let edit = start.elapsed() / EDITS as u32;
+
+        println!(
+            "{0}x{0} map: full upload {1:?}, one edit {2:?}",
+            SIZE, full, edit
+        );
     }
 }
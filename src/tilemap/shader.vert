@@ -12,15 +12,29 @@ layout(binding = 0) uniform Transforms{
 	mat4 world_to_screen;
 };
 
+// Must match EMPTY_TILE in tilemap/mod.rs
+const uint EMPTY_TILE = 0xFFFFFFFF;
+
 struct Tile{
 	uint sheet_pos;
-	uint grid_pos;
 };
 
 layout(binding = 1 ) buffer TilemapData {
     vec2 tile_size; 
 	uint grid_width;
 	uint sheet_width;
+	// Distance between the starts of neighbouring tiles, bigger than tile_size with padding
+	vec2 tile_stride;
+	vec2 sheet_margin;
+};
+
+layout(push_constant) uniform constants {
+	vec2 origin;
+	vec2 cell_size;
+};
+
+// One tile per cell, indexed by grid position
+layout(binding = 2 ) buffer Tiles {
     Tile tiles[]; 
 };
 
@@ -29,14 +43,21 @@ void main() {
 	fragColor = color;
 
 	Tile tile = tiles[gl_InstanceIndex];
+
+	if (tile.sheet_pos == EMPTY_TILE) {
+		// Place every vertex outside the clip volume so the whole quad is culled
+		gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+		return;
+	}
+
 	uint sheet_x = tile.sheet_pos % sheet_width;
 	uint sheet_y = tile.sheet_pos / sheet_width;
 
 	
-	uv = tile_size * vec2(sheet_x, sheet_y) + vec2(position.x, 1 - position.y) * tile_size;
+	uv = sheet_margin + tile_stride * vec2(sheet_x, sheet_y) + vec2(position.x, 1 - position.y) * tile_size;
 
-	uint grid_x = tile.grid_pos % grid_width;
-	uint grid_y = tile.grid_pos / grid_width;
+	uint grid_x = uint(gl_InstanceIndex) % grid_width;
+	uint grid_y = uint(gl_InstanceIndex) / grid_width;
 
-    gl_Position = vec4(position + vec2(grid_x, grid_y) , 0.0, 1.0) * world_to_screen;
-}
\ No newline at end of file
+    gl_Position = vec4(origin + (position + vec2(grid_x, grid_y)) * cell_size , 0.0, 1.0) * world_to_screen;
+}
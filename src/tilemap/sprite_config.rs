@@ -1,14 +1,18 @@
 use std::{
-    collections::{HashMap, HashSet},
-    fmt,
-    io::BufReader,
+    collections::HashMap,
+    fmt, io,
     ops::{Index, IndexMut},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
 use bevy_ecs::prelude as ecs;
-use rand::{prelude::IteratorRandom, Rng};
+use rand::{prelude::SliceRandom, Rng};
 
-use super::{Orientation, TileRequirements};
+use super::{parse_config, ConfigError, Orientation, TileRequirements, CONFIG_VERSION};
 use serde::{
     de::{self, Unexpected, Visitor},
     Deserialize, Deserializer, Serialize, Serializer,
@@ -17,12 +21,19 @@ use std::str::FromStr;
 ///Store config for a tilemap sprite
 #[derive(ecs::Component, Serialize, Deserialize)]
 pub struct TilemapSpriteConfig {
+    ///Layout of the file this was loaded from, see [`CONFIG_VERSION`]
+    pub(super) version: u64,
+
     ///Valid placements for tile (usize,usize)
     pub orientations: HashMap<GridCoordinate, TileRequirements>,
 
     #[serde(skip)]
     coordinates: CoordinateSet,
 
+    ///The file this was loaded from, and where [`TilemapSpriteConfig::save`] writes to
+    #[serde(skip)]
+    source: Option<PathBuf>,
+
     /// Amount of tiles horizontally
     pub grid_width: usize,
     pub grid_height: usize,
@@ -30,11 +41,26 @@ pub struct TilemapSpriteConfig {
     ///Width of a tile (sub-sprite) inside the grid
     pub tile_width: usize,
     pub tile_height: usize,
+
+    ///Pixels between neighbouring tiles
+    pub padding: usize,
+    ///Pixels between the edge of the sheet and the first tile
+    pub margin: usize,
+
+    ///Size of the sheet in pixels, which the grid was worked out from
+    pub image_width: usize,
+    pub image_height: usize,
+
+    ///Bumped whenever the layout or rules change, so tilemaps know to redraw
+    ///
+    ///Shared, so tilemaps can check it without locking the config every frame
+    #[serde(skip)]
+    revision: Arc<AtomicU64>,
 }
 
 struct CoordinateSet {
-    // store coordinate for all possible orientations
-    coordinates: HashMap<u8, HashSet<GridCoordinate>>,
+    // store coordinate for all possible orientations, along with how likely each is to be picked
+    coordinates: HashMap<u8, Vec<(GridCoordinate, u32)>>,
 }
 impl Default for CoordinateSet {
     fn default() -> Self {
@@ -45,25 +71,22 @@ impl Default for CoordinateSet {
 }
 
 impl CoordinateSet {
-    pub fn insert(&mut self, k: Orientation, v: GridCoordinate) {
-        if let Some(s) = self.coordinates.get_mut(&k.bits) {
-            s.insert(v);
-        } else {
-            self.coordinates.insert(k.bits, [v].into());
-        }
+    pub fn insert(&mut self, k: Orientation, v: GridCoordinate, weight: u32) {
+        self.coordinates
+            .entry(k.bits)
+            .or_default()
+            .push((v, weight));
     }
 
     fn get(&self, index: Orientation) -> Option<GridCoordinate> {
         let mut rng = rand::thread_rng();
 
-        if let Some(s) = self.coordinates.get(&(index.bits)) {
-            if let Some(c) = s.iter().choose(&mut rng) {
-                Some(*c)
-            } else {
-                None
-            }
-        } else {
-            None
+        let s = self.coordinates.get(&(index.bits))?;
+
+        match s.choose_weighted(&mut rng, |(_, w)| *w) {
+            Ok((c, _)) => Some(*c),
+            //Every weight is zero, so fall back to picking evenly
+            Err(_) => s.choose(&mut rng).map(|(c, _)| *c),
         }
     }
 }
@@ -139,35 +162,177 @@ impl<'de> Deserialize<'de> for GridCoordinate {
 }
 
 impl TilemapSpriteConfig {
-    pub fn new_or_load(asset: &'static str, grid_width: usize, grid_height: usize) -> Self {
-        let mut config = match std::fs::File::open(asset) {
-            Ok(file) => serde_json::from_reader(BufReader::new(file)).unwrap(),
-            Err(_) => TilemapSpriteConfig::new(grid_width, grid_height),
-        };
+    ///Load the config at `asset`, or create an empty one that will be saved there if there
+    ///isn't a file there yet
+    pub fn new_or_load(
+        asset: impl AsRef<Path>,
+        grid_width: usize,
+        grid_height: usize,
+    ) -> Result<Self, ConfigError> {
+        match Self::load(&asset) {
+            Err(ConfigError::Io { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+                let mut config = TilemapSpriteConfig::new(grid_width, grid_height);
+                config.source = Some(asset.as_ref().to_owned());
+                Ok(config)
+            }
+            r => r,
+        }
+    }
+
+    ///Load a config, upgrading it if it was saved by an older version
+    pub fn load(asset: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let file = asset.as_ref();
 
+        let json = std::fs::read_to_string(file).map_err(|source| ConfigError::Io {
+            file: file.to_owned(),
+            source,
+        })?;
+
+        let mut config = parse_config(&json, file)?;
+
+        config.source = Some(file.to_owned());
         config.sync_coordinates();
 
-        config
+        Ok(config)
     }
 
-    pub fn save(&self, asset: &'static str) {
-        let json = serde_json::to_string(self).unwrap();
-        std::fs::write(asset, json).unwrap();
+    ///The file this config was loaded from or last saved to
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    ///Write back to the file this config came from
+    pub fn save(&self) -> Result<(), ConfigError> {
+        match &self.source {
+            Some(file) => self.write(file),
+            None => Err(ConfigError::NoSource),
+        }
+    }
+
+    ///Write to a new file, which later saves and reloads will use
+    pub fn save_as(&mut self, asset: impl AsRef<Path>) -> Result<(), ConfigError> {
+        self.write(asset.as_ref())?;
+        self.source = Some(asset.as_ref().to_owned());
+
+        Ok(())
+    }
+
+    ///Throw away any changes and load the file again, keeping the current config if that fails
+    pub fn reload(&mut self) -> Result<(), ConfigError> {
+        let file = self.source.as_ref().ok_or(ConfigError::NoSource)?;
+        let revision = self.revision.clone();
+
+        *self = Self::load(file)?;
+        //Keep the counter tilemaps are watching
+        self.revision = revision;
+        self.revision.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    fn write(&self, file: &Path) -> Result<(), ConfigError> {
+        let json = serde_json::to_string(self).map_err(|source| ConfigError::Syntax {
+            file: file.to_owned(),
+            source,
+        })?;
+
+        std::fs::write(file, json).map_err(|source| ConfigError::Io {
+            file: file.to_owned(),
+            source,
+        })
     }
 
     pub fn new(grid_width: usize, grid_height: usize) -> Self {
         Self {
+            version: CONFIG_VERSION,
             tile_width: 8,
             tile_height: 8,
             grid_width,
             grid_height,
             orientations: Default::default(),
             coordinates: Default::default(),
+            source: None,
+            padding: 0,
+            margin: 0,
+            image_width: grid_width * 8,
+            image_height: grid_height * 8,
+            revision: Default::default(),
         }
     }
 
+    ///Changes every time [`TilemapSpriteConfig::sync_coordinates`] is called
+    pub fn revision(&self) -> u64 {
+        self.revision.load(Ordering::Relaxed)
+    }
+
+    ///The counter behind [`TilemapSpriteConfig::revision`], which can be read without a lock
+    pub fn revision_counter(&self) -> Arc<AtomicU64> {
+        self.revision.clone()
+    }
+
+    ///How many tiles fit across and down a sheet of `image_size` pixels with the current tile
+    ///size, padding and margin
+    pub fn grid_for(&self, image_size: [u32; 2]) -> (usize, usize) {
+        let fit = |image: u32, tile: usize| {
+            ((image as usize).saturating_sub(2 * self.margin) + self.padding)
+                / (tile + self.padding).max(1)
+        };
+
+        (
+            fit(image_size[0], self.tile_width),
+            fit(image_size[1], self.tile_height),
+        )
+    }
+
+    ///Work out how many tiles fit on a sheet of `image_size` pixels with the current tile size,
+    ///padding and margin
+    ///
+    ///## Returns
+    ///How many rules were dropped because their tile is no longer on the sheet
+    pub fn recompute_grid(&mut self, image_size: [u32; 2]) -> usize {
+        let (grid_width, grid_height) = self.grid_for(image_size);
+
+        self.image_width = image_size[0] as usize;
+        self.image_height = image_size[1] as usize;
+        self.grid_width = grid_width;
+        self.grid_height = grid_height;
+
+        let (grid_width, grid_height) = (self.grid_width, self.grid_height);
+        let before = self.orientations.len();
+        self.orientations
+            .retain(|c, _| c.x < grid_width && c.y < grid_height);
+
+        self.sync_coordinates();
+
+        before - self.orientations.len()
+    }
+
+    ///Convert a size in pixels on the sheet to UV space
+    ///
+    ///An empty sheet is treated as a single pixel, rather than giving infinite UVs
+    fn to_uv(&self, width: usize, height: usize) -> [f32; 2] {
+        [
+            width as f32 / self.image_width.max(1) as f32,
+            height as f32 / self.image_height.max(1) as f32,
+        ]
+    }
+
+    ///Size of a single tile in UV space
     pub fn tile_size_uv(&self) -> [f32; 2] {
-        [1.0 / self.grid_width as f32, 1.0 / self.grid_height as f32]
+        self.to_uv(self.tile_width, self.tile_height)
+    }
+
+    ///Distance from the start of one tile to the start of the next in UV space, including padding
+    pub fn tile_stride_uv(&self) -> [f32; 2] {
+        self.to_uv(
+            self.tile_width + self.padding,
+            self.tile_height + self.padding,
+        )
+    }
+
+    ///Position of the first tile in UV space
+    pub fn margin_uv(&self) -> [f32; 2] {
+        self.to_uv(self.margin, self.margin)
     }
 
     pub fn grid_width(&self) -> u32 {
@@ -176,11 +341,26 @@ impl TilemapSpriteConfig {
 
     pub fn position_uv(&self, x: usize, y: usize) -> ([f32; 2], [f32; 2]) {
         let [tile_width, tile_height] = self.tile_size_uv();
+        let [stride_x, stride_y] = self.tile_stride_uv();
+        let [margin_x, margin_y] = self.margin_uv();
 
-        (
-            [tile_width * x as f32, tile_height * y as f32],
-            [tile_width * (x + 1) as f32, tile_height * (y + 1) as f32],
-        )
+        let min = [
+            margin_x + stride_x * x as f32,
+            margin_y + stride_y * y as f32,
+        ];
+
+        (min, [min[0] + tile_width, min[1] + tile_height])
+    }
+
+    ///UV rectangle of the tile the renderer numbers `sheet_pos`, counting along each row in turn
+    ///
+    ///[`None`] if the sheet is too small to hold a single column of tiles
+    pub fn sheet_pos_uv(&self, sheet_pos: usize) -> Option<([f32; 2], [f32; 2])> {
+        if self.grid_width == 0 {
+            return None;
+        }
+
+        Some(self.position_uv(sheet_pos % self.grid_width, sheet_pos / self.grid_width))
     }
 
     /// Search the spritemap for this orientation of tile,
@@ -191,6 +371,10 @@ impl TilemapSpriteConfig {
     }
     /// Sync the coordinates array to the orientations hashmap
     pub fn sync_coordinates(&mut self) {
+        //Start again, so removed or edited rules don't leave stale coordinates behind
+        self.coordinates = Default::default();
+        self.revision.fetch_add(1, Ordering::Relaxed);
+
         for (k, v) in &mut self.orientations {
             let mut coords = Vec::new();
             //Push the first coordinate
@@ -206,10 +390,10 @@ impl TilemapSpriteConfig {
                         let mut dirs = c.dirs;
 
                         dirs[i] = Some(true);
-                        n_coords.push(TileRequirements { dirs });
+                        n_coords.push(TileRequirements { dirs, ..c });
 
                         dirs[i] = Some(false);
-                        n_coords.push(TileRequirements { dirs });
+                        n_coords.push(TileRequirements { dirs, ..c });
                     }
 
                     coords = n_coords;
@@ -217,7 +401,7 @@ impl TilemapSpriteConfig {
             }
             for c in coords {
                 // Add to the set of coords that are valid in this orientation
-                self.coordinates.insert(c.into(), *k);
+                self.coordinates.insert(c.into(), *k, v.weight);
             }
         }
     }
@@ -271,6 +455,7 @@ mod tests {
                     Some(true),
                     Some(true),
                 ],
+                ..Default::default()
             },
         );
 
@@ -287,6 +472,7 @@ mod tests {
                     Some(true),
                     Some(true),
                 ],
+                ..Default::default()
             },
         );
         // Should insert into NONE and S
@@ -303,6 +489,7 @@ mod tests {
                     Some(false),
                     Some(false),
                 ],
+                ..Default::default()
             },
         );
 
@@ -316,4 +503,97 @@ mod tests {
         // Nothing has set this
         assert!(c.find_tile_index(Orientation::N).is_none());
     }
+
+    #[test]
+    fn empty_sheet_uvs() {
+        let mut c = TilemapSpriteConfig::new(4, 4);
+        c.image_width = 0;
+
+        assert!(c.tile_size_uv().iter().all(|uv| uv.is_finite()));
+        assert!(c.position_uv(1, 1).1.iter().all(|uv| uv.is_finite()));
+
+        //Too small for any tiles at all
+        c.recompute_grid([0, 0]);
+        assert_eq!(c.grid_width, 0);
+        assert_eq!(c.sheet_pos_uv(5), None);
+
+        c.recompute_grid([4 * c.tile_width as u32, 4 * c.tile_height as u32]);
+        assert_eq!(c.grid_width, 4);
+        assert_eq!(c.sheet_pos_uv(5), Some(c.position_uv(1, 1)));
+    }
+
+    #[test]
+    fn save_as_and_reload() {
+        let path = std::env::temp_dir().join("mole_man_test.tileset.json");
+
+        let mut c = TilemapSpriteConfig::new(4, 4);
+        assert!(matches!(c.save(), Err(ConfigError::NoSource)));
+
+        c.save_as(&path).unwrap();
+        assert_eq!(c.source(), Some(path.as_path()));
+
+        let counter = c.revision_counter();
+        let before = c.revision();
+
+        c.orientations
+            .insert((1, 1).into(), TileRequirements::default());
+        c.reload().unwrap();
+        assert!(c.orientations.is_empty());
+        //Tilemaps holding the counter still see the reload
+        assert!(counter.load(Ordering::Relaxed) > before);
+
+        c.orientations
+            .insert((1, 1).into(), TileRequirements::default());
+        c.save().unwrap();
+        assert_eq!(
+            TilemapSpriteConfig::load(&path).unwrap().orientations.len(),
+            1
+        );
+
+        std::fs::remove_file(&path).unwrap();
+
+        // A missing file isn't an error, the new config will just be saved there
+        let c = TilemapSpriteConfig::new_or_load(&path, 4, 4).unwrap();
+        assert_eq!(c.source(), Some(path.as_path()));
+        assert!(matches!(
+            TilemapSpriteConfig::load(&path),
+            Err(ConfigError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn sync_forgets_removed_rules() {
+        let mut c = TilemapSpriteConfig::new(4, 4);
+        c.orientations
+            .insert((1, 1).into(), Orientation::all().into());
+        c.sync_coordinates();
+        assert_eq!(c.find_tile_index(Orientation::all()), Some((1, 1).into()));
+
+        c.orientations.remove(&(1, 1).into());
+        c.sync_coordinates();
+        assert_eq!(c.find_tile_index(Orientation::all()), None);
+    }
+
+    #[test]
+    fn recompute_grid() {
+        let mut c = TilemapSpriteConfig::new(16, 8);
+        c.orientations
+            .insert((15, 0).into(), TileRequirements::default());
+        c.orientations
+            .insert((2, 1).into(), TileRequirements::default());
+
+        // 1px margin, then 8px tiles with 2px between them
+        c.padding = 2;
+        c.margin = 1;
+        assert_eq!(c.recompute_grid([128, 64]), 1);
+        assert_eq!((c.grid_width, c.grid_height), (12, 6));
+        assert!(c.orientations.contains_key(&(2, 1).into()));
+
+        let (min, max) = c.position_uv(2, 1);
+        assert_eq!(min, [21.0 / 128.0, 11.0 / 64.0]);
+        assert_eq!(max, [29.0 / 128.0, 19.0 / 64.0]);
+
+        c.tile_width = 200;
+        assert_eq!(c.grid_for([128, 64]), (0, 6));
+    }
 }
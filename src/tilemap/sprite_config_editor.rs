@@ -1,19 +1,54 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 use imgui::TextureId;
 use vulkano::image::StorageImage;
 
-use crate::{imgui_vulkano_renderer::ImGuiRenderer, texture::Texture};
+use crate::{
+    hot_reload::{WatchedFile, POLL_INTERVAL},
+    imgui_vulkano_renderer::ImGuiRenderer,
+    texture::Texture,
+};
 
-use super::{GridCoordinate, Orientation, TilemapSpriteConfig};
+use super::{
+    resolve_region, ConfigEdit, ConfigHistory, DirtyRegion, GridCoordinate, MergeMode, Orientation,
+    Terrain, TileData, TileGrid, TileRequirements, TileStamp, TilemapSpriteConfig, EMPTY_TILE,
+};
 
 pub struct TilemapSpriteConfigEditor {
     target: Arc<Mutex<TilemapSpriteConfig>>,
     tex: TextureId,
     size: [u32; 2],
+    ///The first cell selected, which the requirements editor shows
     selected_tile: GridCoordinate,
+    ///The opposite corner of the selected box from `selected_tile`
+    selection_end: GridCoordinate,
+    ///Screen pixels per sheet pixel
+    zoom: f32,
+    ///Offset of the sheet from the top left of its view, in screen pixels
+    pan: [f32; 2],
+    ///A small map to try the rules out on
+    sandbox: TileGrid,
+    ///What the autotiler picked for each sandbox cell, only redone when something changes
+    /// as it picks randomly between matching tiles
+    sandbox_tiles: Vec<TileData>,
+    retile_sandbox: bool,
+    ///Where "Save As" writes to
+    save_as_path: String,
+    history: ConfigHistory,
+    ///The config's file, so changes made outside the editor can be picked up
+    watch: Option<WatchedFile>,
+    last_check: Instant,
+    status: String,
 }
 
+///Width and height of the preview map, in cells
+const SANDBOX_SIZE: usize = 7;
+///Width and height of a cell in the preview, in pixels
+const SANDBOX_CELL: f32 = 24.0;
+
 impl TilemapSpriteConfigEditor {
     pub fn new(
         renderer: &mut ImGuiRenderer,
@@ -23,67 +58,173 @@ impl TilemapSpriteConfigEditor {
         let ui_tex = renderer.make_ui_texture(tex.clone());
         let id = renderer.textures().insert(ui_tex);
 
-        Self {
+        let (save_as_path, first_rule, watch) = {
+            let config = target.lock().unwrap();
+
+            (
+                config
+                    .source()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default(),
+                config.orientations.get(&(0, 0).into()).copied(),
+                config.source().map(WatchedFile::new),
+            )
+        };
+
+        let mut editor = Self {
+            save_as_path,
+            history: ConfigHistory::new(),
+            watch,
+            last_check: Instant::now(),
+            status: String::new(),
             tex: id,
             target,
             size: tex.get_size(),
             selected_tile: (0, 0).into(),
-        }
-    }
-
-    pub fn run(&mut self, ui: &imgui::Ui) {
-        let mut sprite_config = self.target.lock().unwrap();
-
-        let [x, y] = self.size;
+            selection_end: (0, 0).into(),
+            zoom: 4.0,
+            pan: [0.0, 0.0],
+            sandbox: TileGrid::new(SANDBOX_SIZE, SANDBOX_SIZE),
+            sandbox_tiles: vec![
+                TileData {
+                    sheet_pos: EMPTY_TILE
+                };
+                SANDBOX_SIZE * SANDBOX_SIZE
+            ],
+            retile_sandbox: true,
+        };
 
-        //let uv_x = 32.0 / x as f32;
-        //let uv_y = 16.0 / y as f32;
+        editor.reset_sandbox(first_rule.as_ref());
 
-        let [img_x, img_y] = [x as f32 * 4.0, y as f32 * 4.0];
+        editor
+    }
 
-        let [win_x, win_y] = ui.window_pos();
+    ///Every cell in the box between `selected_tile` and `selection_end`
+    pub fn selected_cells(&self) -> impl Iterator<Item = GridCoordinate> {
+        let (a, b) = (self.selected_tile, self.selection_end);
+        let (min_x, max_x) = (a.x.min(b.x), a.x.max(b.x));
+        let (min_y, max_y) = (a.y.min(b.y), a.y.max(b.y));
 
-        let [s_x, s_y] = ui.cursor_pos();
+        (min_y..=max_y).flat_map(move |y| (min_x..=max_x).map(move |x| (x, y).into()))
+    }
 
-        ui.get_window_draw_list()
-            .add_image(
-                self.tex,
-                [win_x + s_x, win_y + s_y],
-                [win_x + img_x + s_x, win_y + img_y + s_y],
-            )
-            .build();
+    pub fn run(&mut self, ui: &imgui::Ui) {
+        let target = self.target.clone();
+        let mut sprite_config = target.lock().unwrap();
 
-        let tbl = ui
-            .begin_table_with_sizing(
-                "str_id",
-                sprite_config.grid_width,
-                imgui::TableFlags::NO_PAD_INNER_X | imgui::TableFlags::NO_PAD_OUTER_X,
-                [img_x, img_y],
-                0.0,
-            )
-            .unwrap();
+        let previous_tile = self.selected_tile;
+        let mut rules_changed = false;
 
-        for y in 0..sprite_config.grid_height {
-            ui.table_next_row_with_height(imgui::TableRowFlags::empty(), 32.0);
+        self.check_source(&mut sprite_config);
 
-            for x in 0..sprite_config.grid_width {
-                ui.table_next_column();
+        ui.enabled(self.history.can_undo(), || {
+            if ui.button("Undo") {
+                rules_changed |= self.history.undo(&mut sprite_config);
+            }
+        });
+        ui.same_line();
+        ui.enabled(self.history.can_redo(), || {
+            if ui.button("Redo") {
+                rules_changed |= self.history.redo(&mut sprite_config);
+            }
+        });
+
+        //Ctrl+Z to undo, Ctrl+Y or Ctrl+Shift+Z to redo, unless a text box should get them
+        if ui.is_window_focused_with_flags(imgui::WindowFocusedFlags::ROOT_AND_CHILD_WINDOWS)
+            && ui.io().key_ctrl
+            && !ui.io().want_text_input
+        {
+            let shift = ui.io().key_shift;
+
+            if ui.is_key_pressed(imgui::Key::Z) && !shift {
+                rules_changed |= self.history.undo(&mut sprite_config);
+            } else if ui.is_key_pressed(imgui::Key::Y) || ui.is_key_pressed(imgui::Key::Z) {
+                rules_changed |= self.history.redo(&mut sprite_config);
+            }
+        }
 
-                let l = match (x, y).into() {
-                    p if p == self.selected_tile => String::from("X"),
-                    p if sprite_config.orientations.contains_key(&p) => {
-                        format!("[{},{}]", p.x, p.y)
-                    }
-                    p => format!("{},{}", p.x, p.y),
-                };
+        if self.history.is_dirty() {
+            ui.same_line();
+            ui.text_colored([1.0, 0.8, 0.0, 1.0], "Unsaved changes");
+        }
 
-                ui.text_colored([0.0, 0.0, 0.0, 1.0], l);
+        let mut layout = [
+            sprite_config.tile_width as i32,
+            sprite_config.tile_height as i32,
+        ];
+        let mut spacing = [sprite_config.padding as i32, sprite_config.margin as i32];
+
+        let resized = imgui::Drag::new("Cell size")
+            .range(1, 512)
+            .speed(0.2)
+            .build_array(ui, &mut layout);
+        let respaced = imgui::Drag::new("Padding / margin")
+            .range(0, 512)
+            .speed(0.2)
+            .build_array(ui, &mut spacing);
+
+        if resized || respaced {
+            let previous = (
+                sprite_config.tile_width,
+                sprite_config.tile_height,
+                sprite_config.padding,
+                sprite_config.margin,
+            );
+
+            sprite_config.tile_width = layout[0] as usize;
+            sprite_config.tile_height = layout[1] as usize;
+            sprite_config.padding = spacing[0] as usize;
+            sprite_config.margin = spacing[1] as usize;
+
+            //Refuse layouts with no room for a single tile, rather than losing every rule
+            if sprite_config.grid_for(self.size).0 == 0 || sprite_config.grid_for(self.size).1 == 0
+            {
+                sprite_config.tile_width = previous.0;
+                sprite_config.tile_height = previous.1;
+                sprite_config.padding = previous.2;
+                sprite_config.margin = previous.3;
+
+                self.status = String::from("No tiles would fit on the sheet");
+            } else {
+                let dropped = sprite_config.recompute_grid(self.size);
+                if dropped > 0 {
+                    self.status =
+                        format!("{} rules were off the new grid and were removed", dropped);
+                }
+                self.history.mark_changed();
+                self.retile_sandbox = true;
             }
         }
 
-        tbl.end();
+        ui.text(format!(
+            "{}x{} tiles, zoom {:.1}x",
+            sprite_config.grid_width, sprite_config.grid_height, self.zoom
+        ));
+        ui.same_line();
+        if ui.button("Reset view") {
+            self.zoom = 4.0;
+            self.pan = [0.0, 0.0];
+        }
 
-        // detect pressing on image
+        let clamp = |c: GridCoordinate| -> GridCoordinate {
+            (
+                c.x.min(sprite_config.grid_width.saturating_sub(1)),
+                c.y.min(sprite_config.grid_height.saturating_sub(1)),
+            )
+                .into()
+        };
+        self.selected_tile = clamp(self.selected_tile);
+        self.selection_end = clamp(self.selection_end);
+
+        //A config from a file could still have no tiles at all
+        if sprite_config.grid_width > 0 && sprite_config.grid_height > 0 {
+            imgui::ChildWindow::new("sheet")
+                .size([0.0, 300.0])
+                .border(true)
+                .scrollable(false)
+                .scroll_bar(false)
+                .build(ui, || self.sheet(ui, &sprite_config));
+        }
 
         ui.separator();
 
@@ -99,14 +240,45 @@ impl TilemapSpriteConfigEditor {
             .speed(1.0)
             .build(ui, &mut s.1);
 
-        self.selected_tile.x = s.0 as usize;
-        self.selected_tile.y = s.1 as usize;
+        if (s.0 as usize, s.1 as usize) != self.selected_tile.into() {
+            self.selected_tile = (s.0 as usize, s.1 as usize).into();
+            self.selection_end = self.selected_tile;
+        }
+
+        let selected: Vec<GridCoordinate> = self.selected_cells().collect();
+        if selected.len() > 1 {
+            ui.text(format!("{} cells selected", selected.len()));
+        }
 
         if sprite_config.orientations.contains_key(&self.selected_tile) {
             if ui.button("Delete") {
-                sprite_config.orientations.remove(&self.selected_tile);
+                let edits = selected
+                    .iter()
+                    .filter_map(|&at| {
+                        let reqs = *sprite_config.orientations.get(&at)?;
+                        Some(ConfigEdit::DeleteEntry { at, reqs })
+                    })
+                    .collect();
+
+                self.history.push(&mut sprite_config, edits);
+                rules_changed = true;
             } else {
-                let mut o = *sprite_config.orientations.get(&self.selected_tile).unwrap();
+                let o = *sprite_config.orientations.get(&self.selected_tile).unwrap();
+                let mut edits = Vec::new();
+
+                let mut weight = o.weight;
+                if imgui::Drag::new("Weight")
+                    .range(1, 1000)
+                    .speed(0.1)
+                    .build(ui, &mut weight)
+                    && weight != o.weight
+                {
+                    edits.push(ConfigEdit::ChangeWeight {
+                        at: self.selected_tile,
+                        old: o.weight,
+                        new: weight,
+                    });
+                }
 
                 let tbl = ui
                     .begin_table_with_sizing(
@@ -118,8 +290,6 @@ impl TilemapSpriteConfigEditor {
                     )
                     .unwrap();
 
-                let mut changed = false;
-
                 for off_y in -1..=1 {
                     ui.table_next_row_with_height(imgui::TableRowFlags::empty(), 50.0);
 
@@ -128,7 +298,7 @@ impl TilemapSpriteConfigEditor {
 
                         if let Some(dir) = Orientation::orient(off_x, -off_y) {
                             //we know this direction is valid
-                            let condition = o.get_requirement_mut(dir).unwrap();
+                            let condition = *o.get_requirement(dir).unwrap();
 
                             let (label, next_val) = match condition {
                                 None => (format!("? {:?}", dir), Some(true)),
@@ -137,95 +307,396 @@ impl TilemapSpriteConfigEditor {
                             };
 
                             if ui.button(label) {
-                                *condition = next_val;
-                                changed = true;
+                                edits.push(ConfigEdit::ChangeRequirement {
+                                    at: self.selected_tile,
+                                    dir,
+                                    old: condition,
+                                    new: next_val,
+                                });
                             }
                         }
                     }
                 }
 
-                if changed {
-                    println!("Changed!");
-                    sprite_config.orientations.insert(self.selected_tile, o);
+                tbl.end();
+
+                if !edits.is_empty() {
+                    self.history.push(&mut sprite_config, edits);
+                    rules_changed = true;
                 }
+            }
+        } else {
+            if ui.button("Add Entry") {
+                let edits = selected
+                    .into_iter()
+                    .filter(|at| !sprite_config.orientations.contains_key(at))
+                    .map(|at| ConfigEdit::AddEntry {
+                        at,
+                        reqs: TileRequirements::default(),
+                    })
+                    .collect();
+
+                self.history.push(&mut sprite_config, edits);
+                rules_changed = true;
+            }
+        }
+        ui.new_line();
 
-                tbl.end();
+        if rules_changed {
+            sprite_config.sync_coordinates();
+            self.retile_sandbox = true;
+        }
 
-                ui.same_line();
+        if self.selected_tile != previous_tile {
+            self.reset_sandbox(sprite_config.orientations.get(&self.selected_tile));
+        }
 
-                let [mut d_x, mut d_y] = ui.cursor_pos();
+        ui.separator();
+        ui.text("Preview - click to dig or fill");
+        if ui.button("Reset preview") {
+            self.reset_sandbox(sprite_config.orientations.get(&self.selected_tile));
+        }
+        self.sandbox(ui, &sprite_config);
 
-                d_x += win_x;
-                d_y += win_y;
+        ui.separator();
 
-                //Draw a preview of this tile in space
-                ui.get_window_draw_list()
-                    .add_rect(
-                        [d_x, d_y],
-                        [d_x + 150.0, d_y + 150.0],
-                        imgui::ImColor32::WHITE,
-                    )
-                    .build();
+        //TODO: Should this be done automatically? think about this
+        match sprite_config.source() {
+            Some(p) => ui.text(format!("Editing {}", p.display())),
+            None => ui.text("Not saved yet"),
+        }
 
-                let reqs = sprite_config.orientations[&self.selected_tile];
-
-                // Draw the sprite preview -
-                //  a 3 by 3 grid of sprites that show how the selected sprite's orientation
-                //  will interact with it's surroundings
-                for off_x in -1..=1 {
-                    for off_y in -1..=1 {
-                        //Only draw a tile if we have specifically requested it
-                        if let Some((disp_x, disp_y)) =
-                            if let Some(o) = Orientation::orient(off_x, -off_y) {
-                                //Test if this direction has been marked as solid
-
-                                if let Some(Some(true)) = reqs.get_requirement(o) {
-                                    //TODO: Allow placed tiles to also react to their surroundings
-                                    Some((2, 2))
-                                } else {
-                                    None
-                                }
-                            } else {
-                                //display the selected tile
-
-                                Some(self.selected_tile.into())
-                            }
-                        {
-                            let (uv_min, uv_max) = sprite_config.position_uv(disp_x, disp_y);
-
-                            ui.get_window_draw_list()
-                                .add_image(
-                                    self.tex,
-                                    [
-                                        d_x + 50.0 * (off_x as f32 + 1.0),
-                                        d_y + 50.0 * (off_y as f32 + 1.0),
-                                    ],
-                                    [
-                                        d_x + 50.0 * (off_x as f32 + 2.0),
-                                        d_y + 50.0 * (off_y as f32 + 2.0),
-                                    ],
-                                )
-                                .uv_min(uv_min)
-                                .uv_max(uv_max)
-                                .build();
+        if ui.button("Save") {
+            self.status = match sprite_config.save() {
+                Ok(()) => {
+                    self.history.mark_saved();
+                    self.watch.as_mut().map(WatchedFile::sync);
+                    String::from("Saved")
+                }
+                Err(e) => format!("Failed to save: {}", e),
+            };
+        }
+        ui.same_line();
+        if ui.button("Reload") {
+            self.status = match sprite_config.reload() {
+                Ok(()) => {
+                    self.history.clear();
+                    self.watch.as_mut().map(WatchedFile::sync);
+                    String::from("Reloaded")
+                }
+                Err(e) => format!("Failed to reload: {}", e),
+            };
+            self.retile_sandbox = true;
+        }
+
+        ui.input_text("##save_as", &mut self.save_as_path).build();
+        ui.same_line();
+        if ui.button("Save As") {
+            self.status = match sprite_config.save_as(&self.save_as_path) {
+                Ok(()) => {
+                    self.history.mark_saved();
+                    self.watch = sprite_config.source().map(WatchedFile::new);
+                    format!("Saved to {}", self.save_as_path)
+                }
+                Err(e) => format!("Failed to save: {}", e),
+            };
+        }
+
+        if !self.status.is_empty() {
+            ui.text(&self.status);
+        }
+    }
+
+    ///Show a new version of the sheet, such as after it changed on disk
+    ///
+    ///If the sheet changed size, the grid is worked out again
+    pub fn set_texture(&mut self, renderer: &mut ImGuiRenderer, tex: Texture<StorageImage>) {
+        let ui_tex = renderer.make_ui_texture(tex.clone());
+        renderer.textures().replace(self.tex, ui_tex);
+        self.size = tex.get_size();
+
+        let mut sprite_config = self.target.lock().unwrap();
+
+        if [sprite_config.image_width, sprite_config.image_height]
+            != [self.size[0] as usize, self.size[1] as usize]
+        {
+            let dropped = sprite_config.recompute_grid(self.size);
+            self.history.mark_changed();
+
+            self.status = format!("The sheet is now {}x{}", self.size[0], self.size[1]);
+            if dropped > 0 {
+                self.status += &format!(", {} rules were off it and were removed", dropped);
+            }
+        }
+
+        self.retile_sandbox = true;
+    }
+
+    ///Reload the config if its file was changed by something else, unless that would throw
+    ///away edits
+    fn check_source(&mut self, sprite_config: &mut TilemapSpriteConfig) {
+        if self.last_check.elapsed() < POLL_INTERVAL {
+            return;
+        }
+        self.last_check = Instant::now();
+
+        if !self.watch.as_mut().is_some_and(WatchedFile::changed) {
+            return;
+        }
+
+        if self.history.is_dirty() {
+            self.status =
+                String::from("The file changed on disk, Reload to use it and lose your changes");
+            return;
+        }
+
+        self.status = match sprite_config.reload() {
+            Ok(()) => {
+                self.history.clear();
+                self.retile_sandbox = true;
+                String::from("Reloaded after the file changed on disk")
+            }
+            Err(e) => format!("Failed to reload: {}", e),
+        };
+    }
+
+    ///Draw the sheet, with selection by clicking and dragging, zoom with the mouse wheel and
+    ///panning with the middle or right mouse button
+    fn sheet(&mut self, ui: &imgui::Ui, sprite_config: &TilemapSpriteConfig) {
+        let [win_x, win_y] = ui.window_pos();
+        let [s_x, s_y] = ui.cursor_pos();
+        let corner = [win_x + s_x, win_y + s_y];
+
+        //Cover the view with a button, so dragging over it selects cells instead of moving the window
+        ui.invisible_button("sheet", ui.content_region_avail());
+        let (hovered, active) = (ui.is_item_hovered(), ui.is_item_active());
+
+        let io = ui.io();
+
+        if hovered && io.mouse_wheel != 0.0 {
+            //Zoom around the mouse, so the pixel under it stays put
+            let old = self.zoom;
+            self.zoom = (self.zoom * 1.2f32.powf(io.mouse_wheel)).clamp(0.5, 32.0);
+
+            for ((pan, corner), mouse) in self.pan.iter_mut().zip(corner).zip(io.mouse_pos) {
+                let under_mouse = mouse - corner - *pan;
+                *pan -= under_mouse * (self.zoom / old - 1.0);
+            }
+        }
+
+        if hovered
+            && (ui.is_mouse_down(imgui::MouseButton::Middle)
+                || ui.is_mouse_down(imgui::MouseButton::Right))
+        {
+            self.pan[0] += io.mouse_delta[0];
+            self.pan[1] += io.mouse_delta[1];
+        }
+
+        let zoom = self.zoom;
+        let origin = [corner[0] + self.pan[0], corner[1] + self.pan[1]];
+        let stride = [
+            (sprite_config.tile_width + sprite_config.padding) as f32,
+            (sprite_config.tile_height + sprite_config.padding) as f32,
+        ];
+        let margin = sprite_config.margin as f32;
+
+        //Screen space corners of a cell on the sheet
+        let cell_rect = |c: GridCoordinate| {
+            let min = [
+                origin[0] + (margin + c.x as f32 * stride[0]) * zoom,
+                origin[1] + (margin + c.y as f32 * stride[1]) * zoom,
+            ];
+            (
+                min,
+                [
+                    min[0] + sprite_config.tile_width as f32 * zoom,
+                    min[1] + sprite_config.tile_height as f32 * zoom,
+                ],
+            )
+        };
+
+        let cell_at = |pos: f32, origin: f32, stride: f32, count: usize| {
+            ((((pos - origin) / zoom - margin) / stride).max(0.0) as usize).min(count - 1)
+        };
+        let mouse_cell: GridCoordinate = (
+            cell_at(
+                io.mouse_pos[0],
+                origin[0],
+                stride[0],
+                sprite_config.grid_width,
+            ),
+            cell_at(
+                io.mouse_pos[1],
+                origin[1],
+                stride[1],
+                sprite_config.grid_height,
+            ),
+        )
+            .into();
+
+        if hovered && ui.is_mouse_clicked(imgui::MouseButton::Left) {
+            self.selected_tile = mouse_cell;
+            self.selection_end = mouse_cell;
+        } else if active && ui.is_mouse_dragging(imgui::MouseButton::Left) {
+            self.selection_end = mouse_cell;
+        }
+
+        let [x, y] = self.size;
+        let draw_list = ui.get_window_draw_list();
+
+        draw_list
+            .add_image(
+                self.tex,
+                origin,
+                [origin[0] + x as f32 * zoom, origin[1] + y as f32 * zoom],
+            )
+            .build();
+
+        //Green for rules that match exactly one orientation, orange for ones with any "?" left
+        for (&c, reqs) in &sprite_config.orientations {
+            let colour = if reqs.is_complete() {
+                [0.0, 1.0, 0.0, 0.3]
+            } else {
+                [1.0, 0.5, 0.0, 0.3]
+            };
+
+            let (min, max) = cell_rect(c);
+            draw_list.add_rect(min, max, colour).filled(true).build();
+        }
+
+        //Only label cells once they are big enough to fit one
+        if sprite_config.tile_width as f32 * zoom >= 48.0 {
+            for y in 0..sprite_config.grid_height {
+                for x in 0..sprite_config.grid_width {
+                    let l = match (x, y).into() {
+                        p if p == self.selected_tile => String::from("X"),
+                        p if sprite_config.orientations.contains_key(&p) => {
+                            format!("[{},{}]", p.x, p.y)
                         }
+                        p => format!("{},{}", p.x, p.y),
+                    };
+
+                    let (min, _) = cell_rect((x, y).into());
+                    draw_list.add_text([min[0] + 2.0, min[1] + 2.0], [0.0, 0.0, 0.0, 1.0], l);
+                }
+            }
+        }
+
+        if hovered {
+            let (min, max) = cell_rect(mouse_cell);
+            draw_list
+                .add_rect(min, max, [1.0, 1.0, 1.0, 0.8])
+                .thickness(2.0)
+                .build();
+        }
+
+        let (a, b) = (cell_rect(self.selected_tile), cell_rect(self.selection_end));
+        draw_list
+            .add_rect(
+                [a.0[0].min(b.0[0]), a.0[1].min(b.0[1])],
+                [a.1[0].max(b.1[0]), a.1[1].max(b.1[1])],
+                [1.0, 1.0, 0.0, 1.0],
+            )
+            .thickness(2.0)
+            .build();
+    }
+
+    ///Lay the sandbox out as the neighbourhood a rule asks for, in the middle of an empty map
+    fn reset_sandbox(&mut self, reqs: Option<&TileRequirements>) {
+        let mut grid = TileGrid::new(SANDBOX_SIZE, SANDBOX_SIZE);
+        TileStamp::new(SANDBOX_SIZE, SANDBOX_SIZE, Terrain::Empty).paste(
+            &mut grid,
+            0,
+            0,
+            MergeMode::Overwrite,
+        );
+
+        let centre = SANDBOX_SIZE / 2;
+        grid.set_terrain(centre, centre, Terrain::Filled);
+
+        if let Some(reqs) = reqs {
+            for off_x in -1..=1 {
+                for off_y in -1..=1 {
+                    if let Some(Some(true)) =
+                        Orientation::orient(off_x, off_y).and_then(|o| reqs.get_requirement(o))
+                    {
+                        let (x, y) = grid.offset(centre, centre, off_x, off_y).unwrap();
+                        grid.set_terrain(x, y, Terrain::Filled);
                     }
                 }
             }
-        } else {
-            if ui.button("Add Entry") {
-                sprite_config
-                    .orientations
-                    .insert(self.selected_tile, Default::default());
+        }
+
+        self.sandbox = grid;
+        self.retile_sandbox = true;
+    }
+
+    ///Draw the sandbox with the tiles the autotiler picks, toggling cells when clicked
+    fn sandbox(&mut self, ui: &imgui::Ui, sprite_config: &TilemapSpriteConfig) {
+        if self.retile_sandbox {
+            resolve_region(
+                &self.sandbox,
+                sprite_config,
+                DirtyRegion::all(SANDBOX_SIZE, SANDBOX_SIZE),
+                &mut self.sandbox_tiles,
+            );
+            self.retile_sandbox = false;
+        }
+
+        let [win_x, win_y] = ui.window_pos();
+        let [s_x, s_y] = ui.cursor_pos();
+        let origin = [win_x + s_x, win_y + s_y];
+        let size = SANDBOX_CELL * SANDBOX_SIZE as f32;
+
+        if ui.invisible_button("sandbox", [size, size]) {
+            let [mouse_x, mouse_y] = ui.io().mouse_pos;
+            let x = ((mouse_x - origin[0]) / SANDBOX_CELL) as usize;
+            let row = ((mouse_y - origin[1]) / SANDBOX_CELL) as usize;
+
+            if x < SANDBOX_SIZE && row < SANDBOX_SIZE {
+                //Rows are drawn top down, but y points up
+                self.sandbox.toggle(x, SANDBOX_SIZE - 1 - row);
+                self.retile_sandbox = true;
             }
         }
-        ui.new_line();
 
-        //TODO: Should this be done automatically? think about this
-        if ui.button("Save") {
-            //TODO: Filename based asset system would be nice
-            let path = "assets/tileset.png.tileset.json";
-            sprite_config.save(path);
+        let draw_list = ui.get_window_draw_list();
+
+        draw_list
+            .add_rect(
+                origin,
+                [origin[0] + size, origin[1] + size],
+                [0.1, 0.1, 0.1, 1.0],
+            )
+            .filled(true)
+            .build();
+
+        for y in 0..SANDBOX_SIZE {
+            for x in 0..SANDBOX_SIZE {
+                let min = [
+                    origin[0] + x as f32 * SANDBOX_CELL,
+                    origin[1] + (SANDBOX_SIZE - 1 - y) as f32 * SANDBOX_CELL,
+                ];
+                let max = [min[0] + SANDBOX_CELL, min[1] + SANDBOX_CELL];
+
+                let sheet_pos = self.sandbox_tiles[y * SANDBOX_SIZE + x].sheet_pos as usize;
+
+                if sheet_pos as u32 == EMPTY_TILE {
+                    continue;
+                }
+
+                //Nothing to draw from a sheet too small for a single tile
+                let (uv_min, uv_max) = match sprite_config.sheet_pos_uv(sheet_pos) {
+                    Some(uv) => uv,
+                    None => continue,
+                };
+
+                draw_list
+                    .add_image(self.tex, min, max)
+                    .uv_min(uv_min)
+                    .uv_max(uv_max)
+                    .build();
+            }
         }
     }
 }
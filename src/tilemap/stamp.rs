@@ -0,0 +1,332 @@
+use std::{fs::File, io, io::BufReader, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Terrain, Tile, TileGrid};
+
+/// How the cells of a [`TileStamp`] combine with the cells they are pasted over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeMode {
+    /// Every cell is replaced by the stamp
+    Overwrite,
+    /// Only the filled cells of the stamp are copied, so it can only add ground
+    FillOnly,
+    /// Only the empty cells of the stamp are copied, so it can only dig tunnels
+    CarveOnly,
+}
+
+/// A rectangle of terrain copied out of a [`TileGrid`], to be pasted elsewhere or saved as a prefab
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileStamp {
+    width: usize,
+    height: usize,
+    /// Row major, `y * width + x`
+    cells: Vec<Terrain>,
+}
+
+impl TileStamp {
+    /// A stamp of a single terrain
+    pub fn new(width: usize, height: usize, terrain: Terrain) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![terrain; width * height],
+        }
+    }
+
+    /// Copy the cells from `min` (inclusive) to `max` (exclusive), clamped to the grid
+    pub fn capture(grid: &TileGrid, min: (usize, usize), max: (usize, usize)) -> Self {
+        let max = (max.0.min(grid.width()), max.1.min(grid.height()));
+        let (width, height) = (max.0.saturating_sub(min.0), max.1.saturating_sub(min.1));
+
+        let mut cells = Vec::with_capacity(width * height);
+        for y in min.1..max.1 {
+            for x in min.0..max.0 {
+                cells.push(grid.terrain(x, y));
+            }
+        }
+
+        Self {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn terrain(&self, x: usize, y: usize) -> Terrain {
+        self.cells[y * self.width + x]
+    }
+
+    pub fn set_terrain(&mut self, x: usize, y: usize, terrain: Terrain) {
+        self.cells[y * self.width + x] = terrain;
+    }
+
+    /// Build a new stamp, where `source` gives the cell of `self` that ends up at each position
+    fn remap(
+        &self,
+        width: usize,
+        height: usize,
+        source: impl Fn(usize, usize) -> (usize, usize),
+    ) -> Self {
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = source(x, y);
+                cells.push(self.terrain(src_x, src_y));
+            }
+        }
+
+        Self {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    /// Rotate a quarter turn clockwise, as seen in the world with y pointing up
+    pub fn rotate_cw(&self) -> Self {
+        self.remap(self.height, self.width, |x, y| (self.width - 1 - y, x))
+    }
+
+    /// Rotate a quarter turn anticlockwise, as seen in the world with y pointing up
+    pub fn rotate_ccw(&self) -> Self {
+        self.remap(self.height, self.width, |x, y| (y, self.height - 1 - x))
+    }
+
+    /// Mirror left to right
+    pub fn flip_x(&self) -> Self {
+        self.remap(self.width, self.height, |x, y| (self.width - 1 - x, y))
+    }
+
+    /// Mirror top to bottom
+    pub fn flip_y(&self) -> Self {
+        self.remap(self.width, self.height, |x, y| (x, self.height - 1 - y))
+    }
+
+    /// Paste with the bottom left of the stamp at `[x][y]`, clipping anything off the grid
+    ///
+    /// Orientations are recalculated inside the pasted area and along its edges
+    ///
+    /// ## Returns
+    /// Every cell that changed terrain, along with the tile that was there before
+    pub fn paste(
+        &self,
+        grid: &mut TileGrid,
+        x: usize,
+        y: usize,
+        mode: MergeMode,
+    ) -> Vec<(usize, usize, Tile)> {
+        let mut changed = Vec::new();
+
+        for stamp_y in 0..self.height.min(grid.height().saturating_sub(y)) {
+            for stamp_x in 0..self.width.min(grid.width().saturating_sub(x)) {
+                let terrain = self.terrain(stamp_x, stamp_y);
+
+                let copy = match mode {
+                    MergeMode::Overwrite => true,
+                    MergeMode::FillOnly => terrain == Terrain::Filled,
+                    MergeMode::CarveOnly => terrain == Terrain::Empty,
+                };
+
+                let (grid_x, grid_y) = (x + stamp_x, y + stamp_y);
+                let old = *grid.tile(grid_x, grid_y);
+
+                if copy && grid.write_terrain(grid_x, grid_y, terrain) {
+                    changed.push((grid_x, grid_y, old));
+                }
+            }
+        }
+
+        grid.update_orientations(
+            (x.saturating_sub(1), y.saturating_sub(1)),
+            (
+                x.saturating_add(self.width + 1),
+                y.saturating_add(self.height + 1),
+            ),
+        );
+
+        changed
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let json = serde_json::to_string(self)?;
+        std::fs::write(path, json)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let stamp: Self = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+        if stamp.cells.len() != stamp.width * stamp.height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "stamp is {}x{} but has {} cells",
+                    stamp.width,
+                    stamp.height,
+                    stamp.cells.len()
+                ),
+            ));
+        }
+
+        Ok(stamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::{assert_orientations_eq, Legend, Orientation};
+
+    /// An L shape, filled along the bottom and up the left
+    ///
+    /// ```text
+    /// #..
+    /// ###
+    /// ```
+    fn l_shape() -> TileStamp {
+        let mut s = TileStamp::new(3, 2, Terrain::Empty);
+        s.set_terrain(0, 0, Terrain::Filled);
+        s.set_terrain(1, 0, Terrain::Filled);
+        s.set_terrain(2, 0, Terrain::Filled);
+        s.set_terrain(0, 1, Terrain::Filled);
+        s
+    }
+
+    #[test]
+    fn rotation() {
+        let s = l_shape();
+
+        let cw = s.rotate_cw();
+        assert_eq!((cw.width(), cw.height()), (2, 3));
+        // ##
+        // #.
+        // #.
+        assert_eq!(cw.terrain(0, 0), Terrain::Filled);
+        assert_eq!(cw.terrain(0, 2), Terrain::Filled);
+        assert_eq!(cw.terrain(1, 2), Terrain::Filled);
+        assert_eq!(cw.terrain(1, 0), Terrain::Empty);
+
+        assert_eq!(cw.rotate_ccw(), s);
+        assert_eq!(s.rotate_cw().rotate_cw(), s.flip_x().flip_y());
+        assert_eq!(s.rotate_cw().rotate_cw().rotate_cw().rotate_cw(), s);
+        assert_eq!(s.flip_x().flip_x(), s);
+    }
+
+    #[test]
+    fn capture_and_paste() {
+        let mut grid = TileGrid::new(6, 6);
+        l_shape().paste(&mut grid, 0, 0, MergeMode::Overwrite);
+
+        let copy = TileStamp::capture(&grid, (0, 0), (3, 2));
+        assert_eq!(copy, l_shape());
+
+        // Clamped to the grid
+        let edge = TileStamp::capture(&grid, (4, 4), (10, 10));
+        assert_eq!((edge.width(), edge.height()), (2, 2));
+
+        let changed = copy.paste(&mut grid, 3, 4, MergeMode::Overwrite);
+        assert_eq!(TileStamp::capture(&grid, (3, 4), (6, 6)), l_shape());
+        assert_eq!(changed.len(), 2);
+        assert!(changed
+            .iter()
+            .all(|(_, _, old)| old.terrain() == Terrain::Filled));
+    }
+
+    #[test]
+    fn merge_modes() {
+        let empty = || {
+            let mut g = TileGrid::new(3, 2);
+            TileStamp::new(3, 2, Terrain::Empty).paste(&mut g, 0, 0, MergeMode::Overwrite);
+            g
+        };
+
+        let mut g = empty();
+        l_shape().paste(&mut g, 0, 0, MergeMode::CarveOnly);
+        assert_eq!(
+            TileStamp::capture(&g, (0, 0), (3, 2)),
+            TileStamp::new(3, 2, Terrain::Empty)
+        );
+
+        let mut g = empty();
+        l_shape().paste(&mut g, 0, 0, MergeMode::FillOnly);
+        assert_eq!(TileStamp::capture(&g, (0, 0), (3, 2)), l_shape());
+
+        let mut g = TileGrid::new(3, 2);
+        l_shape().paste(&mut g, 0, 0, MergeMode::FillOnly);
+        assert_eq!(
+            TileStamp::capture(&g, (0, 0), (3, 2)),
+            TileStamp::new(3, 2, Terrain::Filled)
+        );
+    }
+
+    #[test]
+    fn edge_orientations() {
+        let mut grid = TileGrid::new(4, 4);
+        TileStamp::new(2, 2, Terrain::Empty).paste(&mut grid, 1, 1, MergeMode::Overwrite);
+
+        // Outside the pasted area, but now next to a hole
+        assert_eq!(
+            *grid.tile(0, 1),
+            Tile::Filled(Orientation::all() - Orientation::E - Orientation::NE)
+        );
+        assert_eq!(
+            *grid.tile(3, 3),
+            Tile::Filled(Orientation::all() - Orientation::SW)
+        );
+        // Only touches the hole diagonally
+        assert_eq!(
+            *grid.tile(0, 3),
+            Tile::Filled(Orientation::all() - Orientation::SE)
+        );
+    }
+
+    #[test]
+    fn paste_matches_toggling() {
+        let mut pasted = TileGrid::new(5, 4);
+        l_shape().paste(&mut pasted, 1, 1, MergeMode::CarveOnly);
+
+        let mut toggled = TileGrid::new(5, 4);
+        for (x, y) in [(2, 2), (3, 2)] {
+            toggled.toggle(x, y);
+        }
+
+        assert_orientations_eq(&toggled, &pasted);
+        assert_orientations_eq(
+            &TileGrid::from_ascii(
+                "
+                #####
+                ##..#
+                #####
+                #####
+                ",
+                &Legend::default(),
+            )
+            .unwrap(),
+            &pasted,
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join("mole_man_test.stamp.json");
+
+        l_shape().save(&path).unwrap();
+        assert_eq!(TileStamp::load(&path).unwrap(), l_shape());
+
+        std::fs::write(&path, r#"{"width":2,"height":2,"cells":["Empty"]}"#).unwrap();
+        assert_eq!(
+            TileStamp::load(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
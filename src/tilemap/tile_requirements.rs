@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use super::Orientation;
 
-#[derive(Default, Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 /// 0   `n: Option<bool>`
 ///
 /// 1   `s: Option<bool>`
@@ -20,6 +20,17 @@ use super::Orientation;
 /// 7   `sw: Option<bool>`
 pub struct TileRequirements {
     pub dirs: [Option<bool>; 8],
+    ///How likely this tile is to be picked over others matching the same orientation
+    pub weight: u32,
+}
+
+impl Default for TileRequirements {
+    fn default() -> Self {
+        Self {
+            dirs: Default::default(),
+            weight: 1,
+        }
+    }
 }
 
 impl From<Orientation> for TileRequirements {
@@ -35,11 +46,17 @@ impl From<Orientation> for TileRequirements {
                 Some(o.contains(Orientation::SE)),
                 Some(o.contains(Orientation::SW)),
             ],
+            weight: 1,
         }
     }
 }
 
 impl TileRequirements {
+    ///If every direction has a requirement, so this matches exactly one orientation
+    pub fn is_complete(&self) -> bool {
+        self.dirs.iter().all(Option::is_some)
+    }
+
     pub fn n_mut(&mut self) -> &mut Option<bool> {
         &mut self.dirs[0]
     }
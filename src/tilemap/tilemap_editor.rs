@@ -0,0 +1,310 @@
+use super::{
+    import_png, Connectivity, CornerRule, Legend, MergeMode, Palette, Pathfinder, TileStamp,
+    TilemapRenderer,
+};
+
+///Tools for editing a placed tilemap, shown alongside its inspector
+pub struct TilemapEditor {
+    ///Bottom left of the selected rectangle of cells
+    selection_min: (usize, usize),
+    selection_size: (usize, usize),
+    stamp: Option<TileStamp>,
+    mode: MergeMode,
+    path: String,
+    image_path: String,
+    palette_path: String,
+    status: String,
+    ///Start and goal of the path being previewed
+    route: Option<((usize, usize), (usize, usize))>,
+    route_cells: Vec<(usize, usize)>,
+}
+
+impl TilemapEditor {
+    pub fn new() -> Self {
+        Self {
+            selection_min: (0, 0),
+            selection_size: (4, 4),
+            stamp: None,
+            mode: MergeMode::Overwrite,
+            path: String::from("assets/room.stamp.json"),
+            image_path: String::from("assets/room.png"),
+            palette_path: String::from("assets/palette.json"),
+            status: String::new(),
+            route: None,
+            route_cells: Vec::new(),
+        }
+    }
+
+    ///The selected cells, `min` inclusive and `max` exclusive
+    pub fn selection(&self) -> ((usize, usize), (usize, usize)) {
+        let (x, y) = self.selection_min;
+        let (w, h) = self.selection_size;
+
+        ((x, y), (x + w, y + h))
+    }
+
+    ///Cells of the previewed path, from start to goal
+    pub fn path(&self) -> &[(usize, usize)] {
+        &self.route_cells
+    }
+
+    pub fn run(
+        &mut self,
+        ui: &imgui::Ui,
+        tilemap: &mut TilemapRenderer,
+        pathfinder: Option<&mut Pathfinder>,
+    ) {
+        let mut cell_size = tilemap.cell_size();
+        if imgui::Drag::new("Cell size")
+            .range(0.1, 16.0)
+            .speed(0.05)
+            .build(ui, &mut cell_size)
+        {
+            tilemap.set_cell_size(cell_size.max(0.1));
+        }
+
+        ui.separator();
+
+        let (width, height) = (tilemap.grid().width(), tilemap.grid().height());
+
+        let mut s = [
+            self.selection_min.0 as i32,
+            self.selection_min.1 as i32,
+            self.selection_size.0 as i32,
+            self.selection_size.1 as i32,
+        ];
+
+        imgui::Drag::new("X")
+            .range(0, width as i32 - 1)
+            .speed(0.2)
+            .build(ui, &mut s[0]);
+        imgui::Drag::new("Y")
+            .range(0, height as i32 - 1)
+            .speed(0.2)
+            .build(ui, &mut s[1]);
+        imgui::Drag::new("Width")
+            .range(1, width as i32)
+            .speed(0.2)
+            .build(ui, &mut s[2]);
+        imgui::Drag::new("Height")
+            .range(1, height as i32)
+            .speed(0.2)
+            .build(ui, &mut s[3]);
+
+        //Drag only clamps while dragging, a typed in value can be anything
+        let (width, height) = (width.max(1) as i32, height.max(1) as i32);
+        s[0] = s[0].clamp(0, width - 1);
+        s[1] = s[1].clamp(0, height - 1);
+        s[2] = s[2].clamp(1, width - s[0]);
+        s[3] = s[3].clamp(1, height - s[1]);
+
+        self.selection_min = (s[0] as usize, s[1] as usize);
+        self.selection_size = (s[2] as usize, s[3] as usize);
+
+        ui.separator();
+
+        if ui.button("Capture") {
+            let (min, max) = self.selection();
+            let stamp = TileStamp::capture(tilemap.grid(), min, max);
+
+            self.status = format!("Captured {}x{}", stamp.width(), stamp.height());
+            self.stamp = Some(stamp);
+        }
+
+        ui.same_line();
+        if ui.button("Copy as text") {
+            let (min, max) = self.selection();
+            let stamp = TileStamp::capture(tilemap.grid(), min, max);
+
+            ui.set_clipboard_text(stamp.to_text(&Legend::default()));
+            self.status = format!("Copied {}x{}", stamp.width(), stamp.height());
+        }
+
+        ui.same_line();
+        if ui.button("Paste text") {
+            match ui.clipboard_text().as_deref().map(TileStamp::from_text) {
+                Some(Ok(stamp)) => {
+                    self.status = format!("Pasted {}x{}", stamp.width(), stamp.height());
+                    self.stamp = Some(stamp);
+                }
+                Some(Err(e)) => self.status = format!("Clipboard isn't a stamp: {}", e),
+                None => self.status = String::from("Clipboard is empty"),
+            }
+        }
+
+        if let Some(stamp) = &mut self.stamp {
+            if ui.button("Rotate CW") {
+                *stamp = stamp.rotate_cw();
+            }
+            ui.same_line();
+            if ui.button("Rotate CCW") {
+                *stamp = stamp.rotate_ccw();
+            }
+            ui.same_line();
+            if ui.button("Flip X") {
+                *stamp = stamp.flip_x();
+            }
+            ui.same_line();
+            if ui.button("Flip Y") {
+                *stamp = stamp.flip_y();
+            }
+
+            ui.radio_button("Overwrite", &mut self.mode, MergeMode::Overwrite);
+            ui.same_line();
+            ui.radio_button("Fill only", &mut self.mode, MergeMode::FillOnly);
+            ui.same_line();
+            ui.radio_button("Carve only", &mut self.mode, MergeMode::CarveOnly);
+
+            if ui.button("Paste at selection") {
+                let (x, y) = self.selection_min;
+                tilemap.paste(stamp, x, y, self.mode);
+            }
+        }
+
+        ui.separator();
+
+        ui.input_text("Prefab", &mut self.path).build();
+
+        if let Some(stamp) = &self.stamp {
+            if ui.button("Save") {
+                self.status = match stamp.save(&self.path) {
+                    Ok(()) => format!("Saved {}", self.path),
+                    Err(e) => format!("Failed to save {}: {}", self.path, e),
+                };
+            }
+            ui.same_line();
+        }
+        if ui.button("Load") {
+            match TileStamp::load(&self.path) {
+                Ok(stamp) => {
+                    self.status = format!(
+                        "Loaded {}x{} from {}",
+                        stamp.width(),
+                        stamp.height(),
+                        self.path
+                    );
+                    self.stamp = Some(stamp);
+                }
+                Err(e) => self.status = format!("Failed to load {}: {}", self.path, e),
+            }
+        }
+
+        ui.separator();
+
+        ui.input_text("Image", &mut self.image_path).build();
+        ui.input_text("Palette", &mut self.palette_path).build();
+
+        if ui.button("Import PNG") {
+            self.import_png();
+        }
+
+        if let Some(pathfinder) = pathfinder {
+            ui.separator();
+            self.pathfinding(ui, tilemap, pathfinder);
+        }
+
+        if !self.status.is_empty() {
+            ui.text(&self.status);
+        }
+    }
+}
+
+impl TilemapEditor {
+    ///Preview the path between opposite corners of the selection
+    fn pathfinding(
+        &mut self,
+        ui: &imgui::Ui,
+        tilemap: &TilemapRenderer,
+        pathfinder: &mut Pathfinder,
+    ) {
+        let mut rules = *pathfinder.rules();
+
+        ui.radio_button("4 way", &mut rules.connectivity, Connectivity::Four);
+        ui.same_line();
+        ui.radio_button(
+            "Cut corners",
+            &mut rules.connectivity,
+            Connectivity::Eight(CornerRule::CutCorners),
+        );
+        ui.radio_button(
+            "One blocked",
+            &mut rules.connectivity,
+            Connectivity::Eight(CornerRule::AllowOneBlocked),
+        );
+        ui.same_line();
+        ui.radio_button(
+            "No corner cutting",
+            &mut rules.connectivity,
+            Connectivity::Eight(CornerRule::NoCornerCutting),
+        );
+
+        if rules != *pathfinder.rules() {
+            pathfinder.set_rules(rules);
+        }
+
+        if ui.button("Find path") {
+            let (min, max) = self.selection();
+            self.route = Some((min, (max.0 - 1, max.1 - 1)));
+        }
+        ui.same_line();
+        if ui.button("Clear path") {
+            self.route = None;
+        }
+
+        self.route_cells.clear();
+
+        //Searched every frame so edits show up, the pathfinder only redoes it after one
+        if let Some((start, goal)) = self.route {
+            match pathfinder.find_path(tilemap.grid(), start, goal) {
+                Some(path) => {
+                    self.route_cells.extend_from_slice(&path.cells);
+                    ui.text(format!(
+                        "{} steps from {:?} to {:?}, cost {}",
+                        path.cells.len() - 1,
+                        start,
+                        goal,
+                        path.cost
+                    ));
+                }
+                None => ui.text(format!("No path from {:?} to {:?}", start, goal)),
+            }
+        }
+    }
+
+    ///Load the image as a stamp, ready to be pasted
+    fn import_png(&mut self) {
+        let map = match Palette::load(&self.palette_path)
+            .and_then(|palette| import_png(&self.image_path, &palette))
+        {
+            Ok(map) => map,
+            Err(e) => {
+                self.status = format!("Failed to import {}: {}", self.image_path, e);
+                return;
+            }
+        };
+
+        let stamp = map.stamp();
+
+        self.status = format!(
+            "Imported {}x{} from {}",
+            stamp.width(),
+            stamp.height(),
+            self.image_path
+        );
+
+        for u in &map.unknown {
+            self.status += &format!(
+                "\n{} isn't in the palette, used by {} pixels starting at {:?}",
+                u.colour, u.count, u.first
+            );
+        }
+
+        self.stamp = Some(stamp);
+    }
+}
+
+impl Default for TilemapEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,5 +1,9 @@
 pub mod position;
+pub mod rotation;
+pub mod scale;
 pub use position::Position;
+pub use rotation::Rotation;
+pub use scale::Scale;
 #[derive(crate::ecs::Component)]
 pub struct Bobble;
 
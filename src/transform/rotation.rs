@@ -0,0 +1,3 @@
+///Counter-clockwise rotation in radians
+#[derive(crate::ecs::Component, Clone, Copy, Debug, Default)]
+pub struct Rotation(pub f32);
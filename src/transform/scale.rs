@@ -0,0 +1,8 @@
+#[derive(crate::ecs::Component, Clone, Copy, Debug)]
+pub struct Scale(pub f32, pub f32);
+
+impl Default for Scale {
+    fn default() -> Self {
+        Self(1.0, 1.0)
+    }
+}
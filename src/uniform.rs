@@ -54,6 +54,12 @@ impl Transformations {
         s
     }
 
+    /// Convert a position in window pixels, with y pointing down, into world space
+    pub fn window_to_world(&self, x: f32, y: f32, width: f32, height: f32) -> Vec2 {
+        // normalise to -1..1 across the window
+        self.screen_to_world((x / width - 0.5) * 2.0, (y / height - 0.5) * 2.0)
+    }
+
     pub fn screen_to_world(&self, x: f32, y: f32) -> Vec2 {
         vec2(
             (x - self.m.c0.w) / self.m.c0.x,